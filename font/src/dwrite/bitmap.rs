@@ -0,0 +1,609 @@
+// Copyright 2016 Joe Wilm, The Alacritty Project Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Post-processing for rasterized glyph buffers
+//!
+//! Buffers are tightly packed RGB coverage, 3 bytes per pixel, as returned by
+//! `BitmapRenderTarget::get_opaque_values_as_mask`.
+use std::cmp;
+
+use ::RasterizedGlyph;
+
+#[inline]
+fn is_covered(buf: &[u8], width: usize, x: usize, y: usize) -> bool {
+    let offset = (y * width + x) * 3;
+    buf[offset..offset + 3].iter().any(|&b| b != 0)
+}
+
+/// Crop a render target buffer to exactly `width` by `height` pixels
+///
+/// GDI may allocate render targets wider than requested to satisfy its own alignment, in which
+/// case the extra pixels at the end of each row are dropped. Missing pixels, should the buffer
+/// be short, are left uncovered.
+pub fn crop(buf: Vec<u8>, width: usize, height: usize) -> Vec<u8> {
+    let row = width * 3;
+    if buf.len() == row * height || height == 0 {
+        return buf;
+    }
+
+    let src_row = buf.len() / height;
+    let mut out = vec![0u8; row * height];
+    for y in 0..height {
+        let len = cmp::min(row, src_row);
+        let src = y * src_row;
+        out[y * row..y * row + len].copy_from_slice(&buf[src..src + len]);
+    }
+
+    out
+}
+
+/// Surround the glyph with a border `size` pixels wide, filled with `fill`
+///
+/// `top` and `left` are adjusted so the ink stays in place.
+pub fn pad(glyph: &mut RasterizedGlyph, size: u32, fill: [u8; 3]) {
+    if size == 0 {
+        return;
+    }
+
+    let size = size as i32;
+    let width = glyph.width + size * 2;
+    let height = glyph.height + size * 2;
+
+    let mut out = Vec::with_capacity((width * height * 3) as usize);
+    for y in 0..height {
+        for x in 0..width {
+            let (gx, gy) = (x - size, y - size);
+            if gx < 0 || gy < 0 || gx >= glyph.width || gy >= glyph.height {
+                out.extend_from_slice(&fill);
+            } else {
+                let offset = ((gy * glyph.width + gx) * 3) as usize;
+                out.extend_from_slice(&glyph.buf[offset..offset + 3]);
+            }
+        }
+    }
+
+    glyph.width = width;
+    glyph.height = height;
+    glyph.top += size;
+    glyph.left += size;
+    glyph.buf = out;
+}
+
+/// Length in bytes of a `width` pixel row padded to a multiple of `alignment`
+pub fn row_stride(width: i32, alignment: usize) -> usize {
+    let alignment = cmp::max(alignment, 1);
+    let row = width as usize * 3;
+    (row + alignment - 1) / alignment * alignment
+}
+
+/// Pad each row of the glyph buffer with zeroes up to a multiple of `alignment` bytes
+///
+/// Returns the resulting stride. GPU texture uploads commonly require 4 or 8 byte rows.
+pub fn pad_rows(glyph: &mut RasterizedGlyph, alignment: usize) -> usize {
+    let row = glyph.width as usize * 3;
+    let stride = row_stride(glyph.width, alignment);
+    if stride == row {
+        return stride;
+    }
+
+    let mut out = Vec::with_capacity(stride * glyph.height as usize);
+    for line in glyph.buf.chunks(row) {
+        out.extend_from_slice(line);
+        out.resize(out.len() + stride - row, 0);
+    }
+
+    glyph.buf = out;
+    stride
+}
+
+/// Remove the interior of a glyph, leaving an outline `stroke` pixels wide
+///
+/// This is an erosion of the coverage mask, not a stroke of the glyph outline: a covered pixel
+/// is kept only if some pixel within `stroke` of it is uncovered or off the edge of the bitmap.
+pub fn hollow(glyph: &mut RasterizedGlyph, stroke: u32) {
+    let width = glyph.width as usize;
+    let height = glyph.height as usize;
+    let stroke = stroke as isize;
+    let mut out = glyph.buf.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            if !is_covered(&glyph.buf, width, x, y) {
+                continue;
+            }
+
+            let mut interior = true;
+            'window: for dy in -stroke..stroke + 1 {
+                for dx in -stroke..stroke + 1 {
+                    let nx = x as isize + dx;
+                    let ny = y as isize + dy;
+                    if nx < 0 || ny < 0 || nx >= width as isize || ny >= height as isize ||
+                        !is_covered(&glyph.buf, width, nx as usize, ny as usize)
+                    {
+                        interior = false;
+                        break 'window;
+                    }
+                }
+            }
+
+            if interior {
+                let offset = (y * width + x) * 3;
+                for b in &mut out[offset..offset + 3] {
+                    *b = 0;
+                }
+            }
+        }
+    }
+
+    glyph.buf = out;
+}
+
+/// Sharpen coverage with an unsharp mask
+///
+/// Each channel is pushed away from its 3x3 box-blurred neighbourhood by `amount`; 0 leaves the
+/// glyph unchanged.
+pub fn sharpen(glyph: &mut RasterizedGlyph, amount: f32) {
+    let width = glyph.width as isize;
+    let height = glyph.height as isize;
+    let mut out = glyph.buf.clone();
+
+    for y in 0..height {
+        for x in 0..width {
+            for i in 0..3 {
+                let mut sum = 0.;
+                for dy in -1..2 {
+                    for dx in -1..2 {
+                        // Replicate edge pixels so borders don't darken the blur
+                        let nx = cmp::min(cmp::max(x + dx, 0), width - 1);
+                        let ny = cmp::min(cmp::max(y + dy, 0), height - 1);
+                        sum += glyph.buf[((ny * width + nx) * 3 + i) as usize] as f32;
+                    }
+                }
+
+                let offset = ((y * width + x) * 3 + i) as usize;
+                let value = glyph.buf[offset] as f32;
+                let sharpened = value + amount * (value - sum / 9.);
+                out[offset] = sharpened.round().max(0.).min(255.) as u8;
+            }
+        }
+    }
+
+    glyph.buf = out;
+}
+
+/// Widest run of pixels in a row that `min_stem` treats as part of a stem
+const STEM_WIDTH: usize = 2;
+
+/// Fewest rows a run of pixels has to continue over for `min_stem` to treat it as a stem
+const STEM_ROWS: usize = 3;
+
+/// Boost faint stems so each has at least a pixel's worth of coverage
+///
+/// Narrow horizontal runs of covered pixels in a channel that continue over several rows are
+/// stems; each run of one is scaled up until its coverage adds up to at least 255, which keeps
+/// stems that rendered thinner than a pixel from fading away. Wider runs and isolated specks of
+/// antialiasing fringe are left alone.
+pub fn min_stem(glyph: &mut RasterizedGlyph) {
+    let width = glyph.width as usize;
+    let height = glyph.height as usize;
+    let stride = width * 3;
+    let source = glyph.buf.clone();
+
+    // Whether any of the pixels from `start` to `end` in `row` has coverage in channel `i`
+    let covered = |row: usize, start: usize, end: usize, i: usize| {
+        (start..end).any(|x| source[row * stride + x * 3 + i] != 0)
+    };
+
+    for y in 0..height {
+        let row = y * stride;
+        for i in 0..3 {
+            let mut x = 0;
+            while x < width {
+                if source[row + x * 3 + i] == 0 {
+                    x += 1;
+                    continue;
+                }
+
+                let start = x;
+                let mut sum = 0.;
+                while x < width && source[row + x * 3 + i] != 0 {
+                    sum += source[row + x * 3 + i] as f32;
+                    x += 1;
+                }
+
+                if sum >= 255. || x - start > STEM_WIDTH {
+                    continue;
+                }
+
+                let above = (0..y).rev().take_while(|&y| covered(y, start, x, i)).count();
+                let below = (y + 1..height).take_while(|&y| covered(y, start, x, i)).count();
+                if above + below + 1 < STEM_ROWS {
+                    continue;
+                }
+
+                let scale = 255. / sum;
+                for j in start..x {
+                    let value = source[row + j * 3 + i] as f32 * scale;
+                    glyph.buf[row + j * 3 + i] = value.ceil().min(255.) as u8;
+                }
+            }
+        }
+    }
+}
+
+/// Push coverage towards fully on or off around `threshold`
+///
+/// Coverage more than `softness` above the threshold becomes 255, coverage more than `softness`
+/// below it becomes 0, and values in between are ramped linearly. The ramp is cut off at the
+/// ends of the coverage range, so no coverage stays 0 and full coverage stays 255.
+pub fn threshold(glyph: &mut RasterizedGlyph, threshold: u8, softness: u8) {
+    let low = (threshold as f32 - softness as f32).max(0.);
+    let high = (threshold as f32 + softness as f32).min(255.);
+
+    for byte in &mut glyph.buf {
+        let value = *byte as f32;
+        *byte = if value >= high {
+            255
+        } else if value <= low {
+            0
+        } else {
+            ((value - low) / (high - low) * 255.).round() as u8
+        };
+    }
+}
+
+/// Convert sRGB encoded values to linear light
+pub fn srgb_to_linear(glyph: &mut RasterizedGlyph) {
+    for byte in &mut glyph.buf {
+        let value = *byte as f32 / 255.;
+        let linear = if value <= 0.04045 {
+            value / 12.92
+        } else {
+            ((value + 0.055) / 1.055).powf(2.4)
+        };
+        *byte = (linear * 255.).round() as u8;
+    }
+}
+
+/// Invert coverage, so fully covered pixels become empty and vice versa
+pub fn invert(glyph: &mut RasterizedGlyph) {
+    for byte in &mut glyph.buf {
+        *byte = 255 - *byte;
+    }
+}
+
+/// Linearly interpolate coverage between `a` and `b`, with `t` = 0 giving `a`
+///
+/// The glyphs may have different bounds; the result covers the union of both, aligned on their
+/// pen positions. `left` and `top` are the pen position within each bitmap.
+pub fn blend(a: &RasterizedGlyph, b: &RasterizedGlyph, t: f32) -> RasterizedGlyph {
+    let left = cmp::max(a.left, b.left);
+    let top = cmp::max(a.top, b.top);
+    let right = cmp::max(a.width - a.left, b.width - b.left);
+    let bottom = cmp::max(a.height - a.top, b.height - b.top);
+    let width = left + right;
+    let height = top + bottom;
+
+    let mut buf = vec![0u8; (width * height * 3) as usize];
+    for &(glyph, weight) in &[(a, 1. - t), (b, t)] {
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let src = ((y * glyph.width + x) * 3) as usize;
+                let dst_x = left - glyph.left + x;
+                let dst_y = top - glyph.top + y;
+                let dst = ((dst_y * width + dst_x) * 3) as usize;
+                for i in 0..3 {
+                    let value = buf[dst + i] as f32 + glyph.buf[src + i] as f32 * weight;
+                    buf[dst + i] = value.round().min(255.) as u8;
+                }
+            }
+        }
+    }
+
+    RasterizedGlyph {
+        c: a.c,
+        width: width,
+        height: height,
+        top: top,
+        left: left,
+        buf: buf,
+    }
+}
+
+/// Stack the coverage of color glyph `layers`, bottom first, each in its RGBA color
+///
+/// Layers are aligned on their pen positions like in `blend`. The result holds premultiplied
+/// RGBA pixels, four bytes each; a layer's coverage of a pixel is the mean of its subpixels.
+pub fn composite(c: char, layers: &[(RasterizedGlyph, [u8; 4])]) -> RasterizedGlyph {
+    let left = cmp::max(layers.iter().map(|&(ref glyph, _)| glyph.left).max().unwrap_or(0), 0);
+    let top = cmp::max(layers.iter().map(|&(ref glyph, _)| glyph.top).max().unwrap_or(0), 0);
+    let right = cmp::max(layers.iter().map(|&(ref glyph, _)| glyph.width - glyph.left).max().unwrap_or(0), 0);
+    let bottom = cmp::max(layers.iter().map(|&(ref glyph, _)| glyph.height - glyph.top).max().unwrap_or(0), 0);
+    let width = left + right;
+    let height = top + bottom;
+
+    let mut buf = vec![0u8; (width * height * 4) as usize];
+    for &(ref glyph, color) in layers {
+        for y in 0..glyph.height {
+            for x in 0..glyph.width {
+                let src = ((y * glyph.width + x) * 3) as usize;
+                let dst_x = left - glyph.left + x;
+                let dst_y = top - glyph.top + y;
+                let dst = ((dst_y * width + dst_x) * 4) as usize;
+
+                let coverage = glyph.buf[src..src + 3].iter().map(|&v| v as f32).sum::<f32>() / (3. * 255.);
+                let alpha = coverage * color[3] as f32 / 255.;
+                for i in 0..3 {
+                    let value = color[i] as f32 * alpha + buf[dst + i] as f32 * (1. - alpha);
+                    buf[dst + i] = value.round().min(255.) as u8;
+                }
+                let value = 255. * alpha + buf[dst + 3] as f32 * (1. - alpha);
+                buf[dst + 3] = value.round().min(255.) as u8;
+            }
+        }
+    }
+
+    RasterizedGlyph {
+        c: c,
+        width: width,
+        height: height,
+        top: top,
+        left: left,
+        buf: buf,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use ::RasterizedGlyph;
+
+    /// A `size`x`size` glyph with every pixel fully covered
+    fn filled(size: i32) -> RasterizedGlyph {
+        RasterizedGlyph {
+            c: 'x',
+            width: size,
+            height: size,
+            top: size,
+            left: 0,
+            buf: vec![255; (size * size * 3) as usize],
+        }
+    }
+
+    fn pixel(glyph: &RasterizedGlyph, x: i32, y: i32) -> u8 {
+        glyph.buf[((y * glyph.width + x) * 3) as usize]
+    }
+
+    #[test]
+    fn crop() {
+        // 3x2 glyph in a render target 5 pixels wide
+        let mut buf = Vec::new();
+        for _ in 0..2 {
+            buf.extend_from_slice(&[255; 9]);
+            buf.extend_from_slice(&[7; 6]);
+        }
+
+        let cropped = super::crop(buf, 3, 2);
+        assert_eq!(cropped.len(), 3 * 2 * 3);
+        assert!(cropped.iter().all(|&b| b == 255));
+
+        let exact = vec![1; 3 * 2 * 3];
+        assert_eq!(super::crop(exact.clone(), 3, 2), exact);
+    }
+
+    #[test]
+    fn pad() {
+        let mut glyph = filled(2);
+        super::pad(&mut glyph, 1, [255, 0, 0]);
+
+        assert_eq!((glyph.width, glyph.height), (4, 4));
+        // `left` is the pen position in the bitmap, which moves right with the border
+        assert_eq!((glyph.top, glyph.left), (3, 1));
+
+        for y in 0..4 {
+            for x in 0..4 {
+                let offset = ((y * 4 + x) * 3) as usize;
+                let rgb = &glyph.buf[offset..offset + 3];
+                if x == 0 || y == 0 || x == 3 || y == 3 {
+                    assert_eq!(rgb, &[255, 0, 0]);
+                } else {
+                    assert_eq!(rgb, &[255, 255, 255]);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn pad_rows() {
+        let mut glyph = filled(3);
+        let stride = super::pad_rows(&mut glyph, 4);
+
+        assert_eq!(stride, 12);
+        assert_eq!(glyph.buf.len(), 12 * 3);
+        for row in glyph.buf.chunks(stride) {
+            assert!(row[..9].iter().all(|&b| b == 255));
+            assert!(row[9..].iter().all(|&b| b == 0));
+        }
+
+        assert_eq!(super::row_stride(3, 8), 16);
+        assert_eq!(super::row_stride(4, 4), 12);
+    }
+
+    #[test]
+    fn sharpen() {
+        // Vertical edge between two columns of medium and bright coverage
+        let mut glyph = filled(6);
+        for (i, byte) in glyph.buf.iter_mut().enumerate() {
+            *byte = if (i / 3) % 6 < 3 { 50 } else { 200 };
+        }
+
+        super::sharpen(&mut glyph, 1.);
+
+        let dark = pixel(&glyph, 2, 2);
+        let bright = pixel(&glyph, 3, 2);
+        assert!(dark < 50 && bright > 200);
+
+        // Flat areas are untouched
+        assert_eq!(pixel(&glyph, 0, 2), 50);
+        assert_eq!(pixel(&glyph, 5, 2), 200);
+    }
+
+    #[test]
+    fn min_stem() {
+        // A faint stem two pixels wide between empty columns
+        let mut glyph = filled(4);
+        for (i, byte) in glyph.buf.iter_mut().enumerate() {
+            *byte = match i / 3 % 4 {
+                1 => 60,
+                2 => 40,
+                _ => 0,
+            };
+        }
+
+        super::min_stem(&mut glyph);
+        for y in 0..4 {
+            assert_eq!(pixel(&glyph, 0, y), 0);
+            assert_eq!(pixel(&glyph, 1, y), 153);
+            assert_eq!(pixel(&glyph, 2, y), 102);
+            assert_eq!(pixel(&glyph, 3, y), 0);
+        }
+
+        // Strokes that are already solid are left alone
+        let mut solid = filled(2);
+        super::min_stem(&mut solid);
+        assert_eq!(solid.buf, filled(2).buf);
+
+        // So are wide runs, and faint pixels that don't continue over enough rows
+        let mut fringe = filled(4);
+        for (i, byte) in fringe.buf.iter_mut().enumerate() {
+            let (x, y) = (i / 3 % 4, i / 12);
+            *byte = if y == 0 || (x == 3 && y == 2) { 20 } else { 0 };
+        }
+        let before = fringe.buf.clone();
+        super::min_stem(&mut fringe);
+        assert_eq!(fringe.buf, before);
+    }
+
+    #[test]
+    fn threshold() {
+        let mut glyph = filled(4);
+        for (i, byte) in glyph.buf.iter_mut().enumerate() {
+            *byte = (i * 5) as u8;
+        }
+        let opaque = |glyph: &RasterizedGlyph| glyph.buf.iter().filter(|&&b| b == 255).count();
+        let before = opaque(&glyph);
+
+        super::threshold(&mut glyph, 128, 32);
+        assert!(opaque(&glyph) > before);
+        assert_eq!(glyph.buf[0], 0);
+        assert_eq!(glyph.buf[47], 255);
+    }
+
+    #[test]
+    fn threshold_with_wide_softness() {
+        let mut glyph = filled(1);
+        glyph.buf = vec![0, 16, 255];
+
+        super::threshold(&mut glyph, 16, 32);
+        assert_eq!(glyph.buf[0], 0);
+        assert_eq!(glyph.buf[2], 255);
+
+        glyph.buf = vec![0, 240, 255];
+        super::threshold(&mut glyph, 240, 32);
+        assert_eq!(glyph.buf[0], 0);
+        assert_eq!(glyph.buf[2], 255);
+    }
+
+    #[test]
+    fn srgb_to_linear() {
+        let mut glyph = filled(1);
+        glyph.buf = vec![0, 128, 255];
+
+        super::srgb_to_linear(&mut glyph);
+        assert_eq!(glyph.buf, vec![0, 55, 255]);
+    }
+
+    #[test]
+    fn invert() {
+        let mut glyph = filled(1);
+        glyph.buf = vec![0, 200, 255];
+
+        super::invert(&mut glyph);
+        assert_eq!(glyph.buf, vec![255, 55, 0]);
+    }
+
+    #[test]
+    fn blend() {
+        let a = filled(2);
+        let mut b = filled(4);
+        for byte in &mut b.buf {
+            *byte = 100;
+        }
+
+        let blended = super::blend(&a, &b, 0.5);
+        assert_eq!((blended.width, blended.height), (4, 4));
+        assert_eq!(blended.top, 4);
+
+        // `a` sits in the bottom left of `b`
+        assert_eq!(pixel(&blended, 0, 3), 178);
+        assert_eq!(pixel(&blended, 3, 0), 50);
+    }
+
+    #[test]
+    fn blend_aligns_pens() {
+        // `a` starts a pixel left of the pen, `b` at the pen
+        let mut a = filled(2);
+        a.left = 1;
+        let b = filled(2);
+
+        let blended = super::blend(&a, &b, 0.5);
+        assert_eq!((blended.width, blended.height), (3, 2));
+        assert_eq!(blended.left, 1);
+
+        assert_eq!(pixel(&blended, 0, 0), 128);
+        assert_eq!(pixel(&blended, 1, 0), 255);
+        assert_eq!(pixel(&blended, 2, 0), 128);
+    }
+
+    #[test]
+    fn composite() {
+        // An opaque red square under a half transparent blue one, a pixel right of the pen
+        let red = filled(2);
+        let mut blue = filled(2);
+        blue.left = -1;
+
+        let glyph = super::composite('x', &[(red, [255, 0, 0, 255]), (blue, [0, 0, 255, 128])]);
+        assert_eq!((glyph.width, glyph.height), (3, 2));
+        assert_eq!(glyph.buf.len(), 3 * 2 * 4);
+
+        let rgba = |x: usize| &glyph.buf[x * 4..x * 4 + 4];
+        assert_eq!(rgba(0), &[255, 0, 0, 255]);
+        assert_eq!(rgba(1), &[127, 0, 128, 255]);
+        assert_eq!(rgba(2), &[0, 0, 128, 128]);
+    }
+
+    #[test]
+    fn hollow() {
+        let mut glyph = filled(9);
+        super::hollow(&mut glyph, 1);
+
+        // Edges survive
+        assert_eq!(pixel(&glyph, 0, 0), 255);
+        assert_eq!(pixel(&glyph, 0, 4), 255);
+        assert_eq!(pixel(&glyph, 8, 8), 255);
+
+        // Interior is cleared
+        assert_eq!(pixel(&glyph, 4, 4), 0);
+        assert_eq!(pixel(&glyph, 2, 2), 0);
+    }
+}
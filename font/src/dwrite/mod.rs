@@ -17,52 +17,928 @@
 // Thanks to @rigtorp for the inspiration, and Joe Wilm for this awesome project.
 // @see: https://github.com/jwilm/alacritty/issues/28
 
-use std::collections::HashMap;
-use super::{FontDesc, RasterizedGlyph, Metrics, Size, FontKey, GlyphKey, Weight, Slant, Style};
-use dwrote::{FontCollection, FontFace, FontWeight, FontStretch, FontStyle, RenderingParams, GdiInterop, DWRITE_MEASURING_MODE_NATURAL, GlyphOffset};
+use std::cell::RefCell;
+use std::cmp;
+use std::collections::{HashMap, HashSet};
+use std::mem;
+use std::panic::{self, AssertUnwindSafe, UnwindSafe};
+use std::ptr;
+use std::rc::Rc;
+
+mod bitmap;
+mod tables;
+
+use super::{FontDesc, RasterizedGlyph, Metrics, Size, FontKey, GlyphKey, Weight, Slant, Style, Rasterize};
+use crossbeam;
+use dwrote::{FontCollection, FontFamily, Font, FontFace, FontWeight, FontStretch, FontStyle, RenderingParams, GdiInterop, GlyphOffset};
+use dwrote::BitmapRenderTarget;
+use dwrote::{DWRITE_MEASURING_MODE, DWRITE_MEASURING_MODE_NATURAL, DWRITE_MEASURING_MODE_GDI_CLASSIC};
+use dwrote::{DWRITE_GLYPH_METRICS, DWRITE_FONT_METRICS, DWRITE_MATRIX};
+use winapi::{BOOL, FALSE, GUID, HRESULT, REFIID, RECT, S_OK};
+use winapi::{IDWriteFactory, IDWriteRenderingParams, IUnknown};
+use winapi::{DWRITE_FACTORY_TYPE, DWRITE_FACTORY_TYPE_SHARED, DWRITE_GLYPH_OFFSET, DWRITE_GLYPH_RUN};
+
+/// Unscaled glyph metrics in font design units, as reported by DirectWrite
+pub type GlyphMetrics = DWRITE_GLYPH_METRICS;
+
+/// Affine transform for glyph runs, as a 2x2 matrix in `m11` to `m22` and a translation in pixels
+///
+/// Points map to `(x * m11 + y * m21 + dx, x * m12 + y * m22 + dy)`, with y growing downwards.
+pub type Transform = DWRITE_MATRIX;
+
+/// How to size glyphs whose side bearings add up to more than their advance
+///
+/// Pathological metrics like these would otherwise produce a negative width.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BearingOverflow {
+    /// Treat the glyph as having no ink
+    Zero,
+    /// Use the advance width as the glyph width
+    Advance,
+}
+
+/// Width of the inked part of a glyph in design units
+fn ink_width(gm: &GlyphMetrics, overflow: BearingOverflow) -> i32 {
+    let width = gm.advanceWidth as i32 - (gm.leftSideBearing + gm.rightSideBearing);
+    if width >= 0 {
+        return width;
+    }
+
+    match overflow {
+        BearingOverflow::Zero => 0,
+        BearingOverflow::Advance => gm.advanceWidth as i32,
+    }
+}
+
+/// Parameters for pushing coverage towards fully on or off, see
+/// `DwroteRasterizer::set_coverage_threshold`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CoverageThreshold {
+    /// Coverage at which pixels switch from off to on
+    pub threshold: u8,
+    /// Distance either side of the threshold over which coverage ramps instead of snapping
+    pub softness: u8,
+}
+
+/// Columns between tab stops
+const TAB_STOP: u32 = 8;
+
+/// How wide a tab is
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TabMode {
+    /// Every tab is a full tab stop wide
+    Fixed,
+    /// Tabs advance to the next tab stop after the current column
+    Elastic,
+}
+
+/// Number of cells a tab at `column` advances by
+fn tab_cells(mode: TabMode, column: u32) -> u32 {
+    match mode {
+        TabMode::Fixed => TAB_STOP,
+        TabMode::Elastic => TAB_STOP - column % TAB_STOP,
+    }
+}
+
+/// When glyphs are hinted to the pixel grid
+///
+/// Hinting keeps small text crisp but distorts the shapes of large glyphs.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Hinting {
+    /// Never hint
+    Off,
+    /// Always hint
+    Full,
+    /// Hint glyphs rendered smaller than the given pixel size
+    Below(f32),
+}
+
+/// Encoding of the color values in a glyph buffer
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorSpace {
+    /// Gamma encoded, as DirectWrite renders
+    Srgb,
+    /// Linear light, for blending in a linear framebuffer
+    Linear,
+}
+
+/// A rasterized glyph tagged with the color space of its buffer
+#[derive(Debug, Clone)]
+pub struct ColorGlyph {
+    pub glyph: RasterizedGlyph,
+    pub color_space: ColorSpace,
+}
+
+/// Underline placement in pixels
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct UnderlineMetrics {
+    /// Offset of the top of the underline from the baseline; negative values are below it
+    pub position: f32,
+    /// Height of the underline
+    pub thickness: f32,
+}
+
+/// Underline position and thickness in design units
+///
+/// Some fonts leave these zeroed, in which case they're synthesized: the underline sits halfway
+/// into the descent and is a fourteenth of an em thick.
+fn underline_design_units(dm: &DWRITE_FONT_METRICS) -> (i32, i32) {
+    if dm.underlinePosition != 0 && dm.underlineThickness != 0 {
+        return (dm.underlinePosition as i32, dm.underlineThickness as i32);
+    }
+
+    let thickness = cmp::max(dm.designUnitsPerEm as i32 / 14, 1);
+    let position = -(dm.descent as i32 / 2);
+    (position, thickness)
+}
+
+/// Position a combining `mark` relative to the pen origin of `base`
+///
+/// This is the fallback for marks without anchor data. Marks whose ink sits above the baseline
+/// are centered over the base, clearing the taller of its ink and the x-height; marks at or
+/// below the baseline are centered under it.
+fn place_mark(base: &RasterizedGlyph, mark: &mut RasterizedGlyph, x_height: i32) {
+    // Space left between the base and the mark
+    let gap = 1;
+
+    // `left` is the pen position within each bitmap, so moving the mark right lowers it
+    mark.left = base.left - (base.width - mark.width) / 2;
+    mark.top = if mark.top > 0 {
+        cmp::max(base.top, x_height) + gap + mark.height
+    } else {
+        cmp::min(base.top - base.height, 0) - gap
+    };
+}
+
+/// Whether `c` is a Han ideograph, the characters unified across Chinese, Japanese and Korean
+///
+/// The same codepoint is drawn differently in each region, so these are what the locale picks
+/// fonts for.
+fn is_han(c: char) -> bool {
+    match c as u32 {
+        0x2E80...0x2FDF | 0x3400...0x4DBF | 0x4E00...0x9FFF | 0xF900...0xFAFF => true,
+        0x20000...0x2FA1F => true,
+        _ => false,
+    }
+}
+
+/// Families with the regional forms of Han ideographs for `locale`, in order of preference
+///
+/// Locales are BCP 47 names like `ja-JP`; unknown locales have no preference.
+fn locale_families(locale: &str) -> &'static [&'static str] {
+    let locale = locale.to_lowercase();
+    if locale.starts_with("ja") {
+        &["Yu Gothic", "Meiryo", "MS Gothic"]
+    } else if locale.starts_with("ko") {
+        &["Malgun Gothic", "Gulim"]
+    } else if locale.starts_with("zh-tw") || locale.starts_with("zh-hk") ||
+              locale.starts_with("zh-mo") || locale.starts_with("zh-hant") {
+        &["Microsoft JhengHei", "PMingLiU"]
+    } else if locale.starts_with("zh") {
+        &["Microsoft YaHei", "SimSun"]
+    } else {
+        &[]
+    }
+}
+
+/// Find a font in `collection` with a glyph for `c`, preferring the given style
+///
+/// Families named in `excluded` are skipped.
+fn find_font_with_char(
+    collection: &FontCollection,
+    c: char,
+    weight: FontWeight,
+    stretch: FontStretch,
+    style: FontStyle,
+    excluded: &[String]
+) -> Option<Font> {
+    for index in 0..collection.get_font_family_count() {
+        let family = collection.get_font_family(index);
+        // Matching in an empty family panics inside dwrote
+        if family.get_font_count() == 0 || excluded.contains(&family.name()) {
+            continue;
+        }
+
+        let font = family.get_first_matching_font(weight, stretch, style);
+        if font.has_character(c as u32) {
+            return Some(font);
+        }
+    }
+
+    None
+}
+
+/// Bucket the fractional part of `offset` into one of `phases` subpixel phases
+fn subpixel_phase(offset: f32, phases: u8) -> u8 {
+    let fract = offset - offset.floor();
+    cmp::min((fract * phases as f32) as u8, phases - 1)
+}
+
+/// Pen position for drawing a glyph so its ink starts at the bitmap origin
+///
+/// With `snap` set, the position is rounded to whole pixels so the glyph isn't blurred across
+/// pixel boundaries by a fractional origin.
+fn glyph_origin(gm: &GlyphMetrics, scale: f32, snap: bool) -> (f32, f32) {
+    let x = (-gm.leftSideBearing) as f32 * scale;
+    let y = (gm.verticalOriginY - gm.topSideBearing) as f32 * scale;
+
+    if snap {
+        (x.round(), y.round())
+    } else {
+        (x, y)
+    }
+}
+
+/// Get the design metrics of the glyph `face` maps `c` to
+fn design_glyph_metrics(face: &FontFace, c: char) -> GlyphMetrics {
+    let index = face.get_glyph_indices(&[c as u32])[0];
+    face.get_design_glyph_metrics(&[index], false)[0]
+}
+
+/// Get the index of the glyph `face` maps `c` to
+///
+/// Looking glyphs up reads the font's file, so this fails with `Error::PlatformError` when the
+/// file can't be read.
+fn glyph_index(face: &FontFace, c: char) -> Result<u16, Error> {
+    platform_call("IDWriteFontFace::GetGlyphIndices", AssertUnwindSafe(|| {
+        face.get_glyph_indices(&[c as u32])[0]
+    }))
+}
+
+/// Get the metrics GDI would use for the glyph at `index` when drawn `em_size` pixels high
+///
+/// They're still in design units, but adjusted so that scaling them to `em_size` gives the
+/// advances GDI's hinting settles on. `use_gdi_natural` picks GDI's natural widths instead of
+/// its classic whole-pixel ones. dwrote doesn't wrap this, so it goes to the COM interface.
+fn gdi_glyph_metrics(
+    face: &FontFace,
+    index: u16,
+    em_size: f32,
+    use_gdi_natural: bool
+) -> Result<GlyphMetrics, Error> {
+    let mut gm: GlyphMetrics = unsafe { mem::zeroed() };
+    let hr = unsafe {
+        (*face.as_ptr()).GetGdiCompatibleGlyphMetrics(em_size, 1., ptr::null(), use_gdi_natural as BOOL,
+                                                      &index, 1, &mut gm, FALSE)
+    };
+
+    if hr != S_OK {
+        return Err(Error::PlatformError("IDWriteFontFace::GetGdiCompatibleGlyphMetrics"));
+    }
+
+    Ok(gm)
+}
+
+/// Get the font metrics GDI would use for `face` when drawn `em_size` pixels high
+fn gdi_font_metrics(face: &FontFace, em_size: f32) -> Result<DWRITE_FONT_METRICS, Error> {
+    let mut dm: DWRITE_FONT_METRICS = unsafe { mem::zeroed() };
+    let hr = unsafe {
+        (*face.as_ptr()).GetGdiCompatibleMetrics(em_size, 1., ptr::null(), &mut dm)
+    };
+
+    if hr != S_OK {
+        return Err(Error::PlatformError("IDWriteFontFace::GetGdiCompatibleMetrics"));
+    }
+
+    Ok(dm)
+}
+
+/// IID of `IDWriteFactory`, which winapi doesn't define
+const IID_IDWRITE_FACTORY: GUID = GUID {
+    Data1: 0xb859ee5a,
+    Data2: 0xd838,
+    Data3: 0x4b5b,
+    Data4: [0xa2, 0xe8, 0x1a, 0xdc, 0x7d, 0x93, 0xdb, 0x48],
+};
+
+#[link(name = "dwrite")]
+extern "system" {
+    fn DWriteCreateFactory(factory_type: DWRITE_FACTORY_TYPE, iid: REFIID, factory: *mut *mut IUnknown) -> HRESULT;
+}
+
+/// Rendering params with a gamma of our choosing, see `DwroteRasterizer::set_gamma`
+///
+/// dwrote can only create rendering params for the primary monitor, so these are created
+/// through COM. Everything besides the gamma is taken from the primary monitor's params.
+struct GammaRenderingParams(*mut IDWriteRenderingParams);
+
+impl GammaRenderingParams {
+    fn create(gamma: f32) -> Result<GammaRenderingParams, Error> {
+        unsafe {
+            let mut factory: *mut IDWriteFactory = ptr::null_mut();
+            let hr = DWriteCreateFactory(DWRITE_FACTORY_TYPE_SHARED, &IID_IDWRITE_FACTORY,
+                                         &mut factory as *mut _ as *mut *mut IUnknown);
+            if hr != S_OK {
+                return Err(Error::PlatformError("DWriteCreateFactory"));
+            }
+
+            let mut monitor: *mut IDWriteRenderingParams = ptr::null_mut();
+            let hr = (*factory).CreateRenderingParams(&mut monitor);
+            if hr != S_OK {
+                (*factory).Release();
+                return Err(Error::PlatformError("IDWriteFactory::CreateRenderingParams"));
+            }
+
+            let mut params: *mut IDWriteRenderingParams = ptr::null_mut();
+            let hr = (*factory).CreateCustomRenderingParams(gamma,
+                                                            (*monitor).GetEnhancedContrast(),
+                                                            (*monitor).GetClearTypeLevel(),
+                                                            (*monitor).GetPixelGeometry(),
+                                                            (*monitor).GetRenderingMode(),
+                                                            &mut params);
+            (*monitor).Release();
+            (*factory).Release();
+
+            if hr != S_OK {
+                return Err(Error::PlatformError("IDWriteFactory::CreateCustomRenderingParams"));
+            }
+
+            Ok(GammaRenderingParams(params))
+        }
+    }
+}
+
+impl Drop for GammaRenderingParams {
+    fn drop(&mut self) {
+        unsafe {
+            (*self.0).Release();
+        }
+    }
+}
+
+/// Draw the glyph at `index` in `face` in white onto `rt`, with the pen at `origin`
+///
+/// dwrote's `draw_glyph_run` only takes its own rendering params, so this goes to the COM
+/// interface to draw with `params`.
+fn draw_glyph_run_with(
+    rt: &BitmapRenderTarget,
+    origin: (f32, f32),
+    measuring_mode: DWRITE_MEASURING_MODE,
+    face: &FontFace,
+    size: f32,
+    index: u16,
+    params: &GammaRenderingParams
+) -> Result<(), Error> {
+    let advance = 0f32;
+    let offset = DWRITE_GLYPH_OFFSET { advanceOffset: 0., ascenderOffset: 0. };
+    let run = DWRITE_GLYPH_RUN {
+        fontFace: unsafe { face.as_ptr() },
+        fontEmSize: size,
+        glyphCount: 1,
+        glyphIndices: &index,
+        glyphAdvances: &advance,
+        glyphOffsets: &offset,
+        isSideways: FALSE,
+        bidiLevel: 0,
+    };
+
+    let mut bounds: RECT = unsafe { mem::zeroed() };
+    // COLORREF is 0x00BBGGRR
+    let hr = unsafe {
+        (*rt.as_ptr()).DrawGlyphRun(origin.0, origin.1, measuring_mode, &run, params.0, 0x00FFFFFF, &mut bounds)
+    };
+
+    if hr != S_OK {
+        return Err(Error::PlatformError("IDWriteBitmapRenderTarget::DrawGlyphRun"));
+    }
+
+    Ok(())
+}
+
+/// Number of cells an `advance` occupies on a grid of `cell` wide columns
+///
+/// Advances round to the nearest whole number of cells, but anything with an advance takes at
+/// least one cell and nothing takes more than two, the width of a double-width character.
+fn cells_for_advance(advance: f32, cell: f32) -> u32 {
+    if advance <= 0. {
+        return 0;
+    }
+
+    (advance / cell).round().max(1.).min(2.) as u32
+}
+
+/// Whether `c` is a Unicode noncharacter
+///
+/// Noncharacters are reserved for internal use and should never be displayed, not even as a
+/// replacement box.
+fn is_noncharacter(c: char) -> bool {
+    let c = c as u32;
+    (c >= 0xFDD0 && c <= 0xFDEF) || (c & 0xFFFE) == 0xFFFE
+}
+
+/// Whether `c` is meant to have no advance, like combining marks and format characters
+///
+/// This covers the general purpose combining blocks, the Hebrew, Arabic, Devanagari and Thai
+/// marks, variation selectors and the common format characters; it isn't a full table of the
+/// Mn, Me and Cf categories.
+fn is_zero_width(c: char) -> bool {
+    match c as u32 {
+        0x0300...0x036F | 0x1AB0...0x1AFF | 0x1DC0...0x1DFF | 0x20D0...0x20FF | 0xFE20...0xFE2F => true,
+        0x0591...0x05BD | 0x05BF | 0x05C1...0x05C2 | 0x05C4...0x05C5 | 0x05C7 => true,
+        0x0610...0x061A | 0x064B...0x065F | 0x0670 | 0x06D6...0x06DC | 0x06DF...0x06E4 => true,
+        0x06E7...0x06E8 | 0x06EA...0x06ED => true,
+        0x0900...0x0902 | 0x093A | 0x093C | 0x0941...0x0948 | 0x094D | 0x0951...0x0957 => true,
+        0x0962...0x0963 | 0x0E31 | 0x0E34...0x0E3A | 0x0E47...0x0E4E => true,
+        0xFE00...0xFE0F | 0xE0100...0xE01EF => true,
+        0x00AD | 0x0600...0x0605 | 0x061C | 0x06DD | 0x180E | 0x202A...0x202E | 0x2066...0x2069 => true,
+        0x200B...0x200F | 0x2060...0x2064 | 0xFEFF => true,
+        _ => false,
+    }
+}
+
+thread_local! {
+    /// Characters `clamp_advance` has already warned about on this thread
+    static NEGATIVE_ADVANCES: RefCell<HashSet<char>> = RefCell::new(HashSet::new());
+}
+
+/// Clamp a negative horizontal advance to zero
+///
+/// `advanceWidth` is unsigned, but malformed fonts can produce values that only make sense as
+/// negative numbers. Taken literally they'd move the pen backwards and corrupt the layout of
+/// everything after `c`. Advances are looked up over and over while laying out text, so each
+/// character is only warned about once.
+fn clamp_advance(mut gm: GlyphMetrics, c: char) -> GlyphMetrics {
+    if (gm.advanceWidth as i32) < 0 {
+        if NEGATIVE_ADVANCES.with(|warned| warned.borrow_mut().insert(c)) {
+            use std::io::Write;
+            let _ = writeln!(&mut ::std::io::stderr(),
+                             "warning: glyph for {:?} has a negative advance; using 0 instead", c);
+        }
+        gm.advanceWidth = 0;
+    }
+
+    gm
+}
+
+/// Fill in a missing horizontal advance from the em square
+///
+/// Some CJK fonts only provide vertical metrics for certain glyphs, leaving them with no
+/// horizontal advance. Their glyphs are designed on a square em, so that's used instead.
+/// This only applies to faces with `vertical_metrics` of their own, see
+/// `has_vertical_metrics`. Characters which are supposed to have no advance are left alone,
+/// and negative advances are clamped to zero rather than filled in.
+fn with_horizontal_advance(mut gm: GlyphMetrics, em: u16, vertical_metrics: bool, c: char) -> GlyphMetrics {
+    if vertical_metrics && gm.advanceWidth == 0 && gm.advanceHeight != 0 && !is_zero_width(c) {
+        gm.advanceWidth = em as u32;
+    }
+
+    clamp_advance(gm, c)
+}
+
+/// Whether `face` has vertical metrics of its own, in `vhea` and `vmtx` tables
+///
+/// DirectWrite makes up a vertical advance for every glyph of faces without them, so there a
+/// glyph with only a vertical advance is just one with no advance.
+fn has_vertical_metrics(face: &FontFace) -> bool {
+    face.get_font_table(opentype_tag(b"vhea")).is_some()
+}
+
+/// Zero width joiner, used to combine emoji into sequences
+const ZWJ: char = '\u{200D}';
+
+/// Split a ZWJ sequence into the characters to draw when it can't be composed
+///
+/// Joiners and emoji/text presentation selectors have no appearance of their own and are
+/// dropped.
+fn zwj_components(sequence: &str) -> Vec<char> {
+    sequence.chars()
+        .filter(|&c| c != ZWJ && c != '\u{FE0E}' && c != '\u{FE0F}')
+        .collect()
+}
+
+/// Byte order mark, also known as zero width no-break space
+const BOM: char = '\u{FEFF}';
+
+/// Whether `c` should be drawn as nothing at all, with no advance
+///
+/// Besides noncharacters, this covers U+FEFF, which is a byte order mark at the start of text
+/// and a zero width no-break space anywhere else; neither has an appearance.
+fn is_invisible(c: char) -> bool {
+    c == BOM || is_noncharacter(c)
+}
+
+/// A glyph with no ink
+fn empty_glyph(c: char) -> RasterizedGlyph {
+    RasterizedGlyph {
+        c: c,
+        top: 0,
+        left: 0,
+        width: 0,
+        height: 0,
+        buf: Vec::new(),
+    }
+}
+
+/// Escape `c` for use inside a JSON string
+fn json_escape(c: char) -> String {
+    match c {
+        '"' => String::from("\\\""),
+        '\\' => String::from("\\\\"),
+        c if (c as u32) < 0x20 => format!("\\u{:04x}", c as u32),
+        c => c.to_string(),
+    }
+}
+
+/// Build a DirectWrite OpenType table tag from its four character name
+fn opentype_tag(tag: &[u8; 4]) -> u32 {
+    (tag[3] as u32) << 24 | (tag[2] as u32) << 16 | (tag[1] as u32) << 8 | tag[0] as u32
+}
+
+/// Run a dwrote call that panics on failure, turning the panic into `Error::PlatformError`
+///
+/// dwrote asserts on the HRESULTs of calls like `GdiInterop::create`, which fail in sessions
+/// without a desktop. Those asserts happen before any COM object is handed out, so they're safe
+/// to unwind from. `call` names the failing call in the error.
+fn platform_call<T, F>(call: &'static str, f: F) -> Result<T, Error>
+    where F: FnOnce() -> T + UnwindSafe
+{
+    panic::catch_unwind(f).map_err(|_| Error::PlatformError(call))
+}
+
+/// Family of the emoji font that ships with Windows
+const EMOJI_FAMILY: &'static str = "Segoe UI Emoji";
+
+/// Italic angle assumed for faces that don't declare one, in degrees
+const DEFAULT_ITALIC_ANGLE: f32 = -12.;
+
+/// Horizontal shift per pixel of height for a glyph leaning at the italic `angle`
+fn italic_shear(angle: f32) -> f32 {
+    (-angle).to_radians().tan()
+}
+
+/// Transform leaning glyphs right by `shear` pixels per pixel of height
+fn shear_matrix(shear: f32) -> DWRITE_MATRIX {
+    DWRITE_MATRIX {
+        m11: 1., m12: 0.,
+        m21: -shear, m22: 1.,
+        dx: 0., dy: 0.,
+    }
+}
+
+/// Get the bitmap size needed to hold a glyph's ink after `transform`, and the pen position in it
+///
+/// The untransformed ink is `width` by `height` pixels with the pen at `x`, `y`, and
+/// `transform` maps points relative to the pen. The bounds are rounded out to whole pixels with
+/// one to spare on each side for antialiasing, so the transformed glyph isn't clipped.
+/// Returns the width, height and pen position in that order.
+fn transformed_bounds(
+    transform: &DWRITE_MATRIX,
+    width: f32,
+    height: f32,
+    x: f32,
+    y: f32
+) -> (f32, f32, f32, f32) {
+    let corners = [(-x, -y), (width - x, -y), (-x, height - y), (width - x, height - y)];
+
+    let (mut left, mut top) = (::std::f32::MAX, ::std::f32::MAX);
+    let (mut right, mut bottom) = (::std::f32::MIN, ::std::f32::MIN);
+    for &(cx, cy) in &corners {
+        let tx = cx * transform.m11 + cy * transform.m21 + transform.dx;
+        let ty = cx * transform.m12 + cy * transform.m22 + transform.dy;
+        left = left.min(tx);
+        right = right.max(tx);
+        top = top.min(ty);
+        bottom = bottom.max(ty);
+    }
+
+    let (left, top) = (left.floor() - 1., top.floor() - 1.);
+    let (right, bottom) = (right.ceil() + 1., bottom.ceil() + 1.);
+    (right - left, bottom - top, -left, -top)
+}
+
+/// Read the italic angle declared in the face's `post` table, in degrees
+///
+/// Negative angles lean to the right, as in the OpenType spec.
+fn italic_angle(face: &FontFace) -> Option<f32> {
+    let post = match face.get_font_table(opentype_tag(b"post")) {
+        Some(post) => post,
+        None => return None,
+    };
+
+    if post.len() < 8 {
+        return None;
+    }
+
+    // 16.16 fixed point, big endian
+    let fixed = (post[4] as i32) << 24 | (post[5] as i32) << 16 | (post[6] as i32) << 8 | post[7] as i32;
+    Some(fixed as f32 / 65536.)
+}
+
+/// Angle a slanted `face` leans at, in degrees
+///
+/// Simulated obliques are upright faces slanted by DirectWrite, so they declare an angle of 0;
+/// they and faces without a `post` table are assumed to use the conventional lean.
+fn slanted_angle(face: &FontFace) -> f32 {
+    match italic_angle(face) {
+        Some(angle) if angle != 0. => angle,
+        _ => DEFAULT_ITALIC_ANGLE,
+    }
+}
+
+/// Numeric style of a resolved font face
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct NumericStyle {
+    /// Weight from 100 (thin) to 900 (black)
+    pub weight: u32,
+    /// Width class from 1 (ultra-condensed) to 9 (ultra-expanded)
+    pub stretch: u32,
+    /// Slant angle in degrees; 0 for upright faces
+    pub slant: f32,
+}
+
+/// Iterator over every glyph in a face, created by `DwroteRasterizer::iterate_glyphs`
+///
+/// Glyphs aren't associated with a character, so their `c` is `'\0'`. Iteration carries on past
+/// glyphs that fail to rasterize.
+pub struct Glyphs<'a> {
+    rasterizer: &'a DwroteRasterizer,
+    key: FontKey,
+    face: &'a FontFace,
+    size: f32,
+    next: u16,
+    count: u16,
+}
+
+impl<'a> Iterator for Glyphs<'a> {
+    type Item = Result<(u16, RasterizedGlyph), Error>;
+
+    fn next(&mut self) -> Option<Result<(u16, RasterizedGlyph), Error>> {
+        if self.next >= self.count {
+            return None;
+        }
+
+        let index = self.next;
+        self.next += 1;
+
+        let glyph = self.rasterizer.rasterize_index(self.face, '\0', index, self.size)
+            .map(|mut glyph| {
+                bitmap::pad_rows(&mut glyph, self.rasterizer.options.row_alignment);
+                (index, glyph)
+            })
+            .map_err(|err| self.rasterizer.font_error(self.key, err));
+        Some(glyph)
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        let remaining = (self.count - self.next) as usize;
+        (remaining, Some(remaining))
+    }
+}
+
+/// Largest em size in pixels that `DwroteRasterizer::set_min_stem` boosts stems at
+///
+/// Regular stems are around a tenth of an em wide, so above this they cover a pixel anyway.
+const MIN_STEM_SIZE: f32 = 10.;
+
+/// Number of threads `rasterize_batch_parallel` spreads its work over
+const BATCH_THREADS: usize = 4;
+
+/// Settings for how glyphs are drawn, kept apart so batch workers can be given a copy
+#[derive(Debug, Clone, Copy)]
+struct GlyphOptions {
+    hollow_stroke: Option<u32>,
+    bearing_overflow: BearingOverflow,
+    snap_origin: bool,
+    sharpen: Option<f32>,
+    row_alignment: usize,
+    padding: (u32, [u8; 3]),
+    gamma: Option<f32>,
+    coverage_threshold: Option<CoverageThreshold>,
+    hinting: Hinting,
+    invert_coverage: bool,
+    min_stem: bool,
+}
+
+/// What a batch worker needs to load a font again on its own thread
+///
+/// dwrote's fonts wrap COM interfaces and aren't `Send`, so workers are given the family and
+/// style to look the font up by instead.
+#[derive(Debug, Clone, PartialEq)]
+struct FontSource {
+    family: String,
+    weight: u32,
+    stretch: u32,
+    style: u32,
+}
+
+impl FontSource {
+    fn of(font: &Font) -> FontSource {
+        FontSource {
+            family: font.family_name(),
+            weight: font.weight().to_u32(),
+            stretch: font.stretch().to_u32(),
+            style: font.style().to_u32(),
+        }
+    }
+}
 
 /// Rasterizes glyphs for a single font face.
 pub struct DwroteRasterizer {
-    library: FontCollection,
+    library: Rc<FontCollection>,
+    gdi_interop: GdiInterop,
+    fallback_collection: Option<FontCollection>,
+    fonts: HashMap<FontKey, Font>,
     faces: HashMap<FontKey, FontFace>,
     keys: HashMap<FontDesc, FontKey>,
+    cache: HashMap<(GlyphKey, Option<u8>), RasterizedGlyph>,
+    fallbacks: HashMap<(FontKey, char), FontKey>,
+    fallback_frozen: bool,
     dpi_x: u32,
     dpi_y: u32,
     dpr: f32,
+    options: GlyphOptions,
+    rendering_params: Option<GammaRenderingParams>,
+    metrics_scale: f32,
+    subpixel_phases: u8,
+    tab_mode: TabMode,
+    locale: Option<String>,
+    baseline_spacing: Option<f32>,
+    lost: HashSet<FontKey>,
 }
 
 impl ::Rasterize for DwroteRasterizer {
     type Err = Error;
 
     fn new(dpi_x: f32, dpi_y: f32, device_pixel_ratio: f32, _: bool) -> Result<DwroteRasterizer, Error> {
+        let collection = platform_call("IDWriteFactory::GetSystemFontCollection", FontCollection::system)?;
+        DwroteRasterizer::with_collection(Rc::new(collection), dpi_x, dpi_y, device_pixel_ratio)
+    }
+
+    fn metrics(&self, key: FontKey, size: Size) -> Result<Metrics, Error> {
+        self.metrics_with_mode(key, size, DWRITE_MEASURING_MODE_NATURAL)
+    }
+
+    fn load_font(&mut self, desc: &FontDesc, _size: Size) -> Result<FontKey, Error> {
+        self.keys
+            .get(&desc.to_owned())
+            .map(|k| Ok(*k))
+            .unwrap_or_else(|| {
+                let font = self.get_font(desc)?;
+                // dwrote panics if this fails. Fonts are validated up front in `get_family`, and
+                // anything that slips through is reported as an error rather than unwinding.
+                let face = platform_call("IDWriteFont::CreateFontFace", AssertUnwindSafe(|| {
+                    font.create_font_face()
+                }))?;
+                let key = FontKey::next();
+                self.faces.insert(key, face);
+                self.fonts.insert(key, font);
+                Ok(key)
+            })
+    }
+
+    fn get_glyph(&mut self, glyph_key: &GlyphKey) -> Result<RasterizedGlyph, Error> {
+        let size = glyph_key.size.as_f32_pts() * self.dpr;
+        let mut glyph = self.rasterize_resolved(glyph_key.font_key, glyph_key.c, |rasterizer, face| {
+            rasterizer.rasterize_glyph(face, glyph_key.c, size)
+        })?;
+
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+        Ok(glyph)
+    }
+}
+
+impl DwroteRasterizer {
+    /// Create a rasterizer that loads fonts from `collection`
+    ///
+    /// Building the system collection enumerates every installed font, so rasterizers can pass
+    /// theirs on with `collection` instead of each building their own.
+    pub fn with_collection(
+        collection: Rc<FontCollection>,
+        dpi_x: f32,
+        dpi_y: f32,
+        device_pixel_ratio: f32
+    ) -> Result<DwroteRasterizer, Error> {
         Ok(DwroteRasterizer {
-            library: FontCollection::system(),
+            library: collection,
+            gdi_interop: platform_call("GdiInterop::create", GdiInterop::create)?,
+            fallback_collection: None,
+            fonts: HashMap::new(),
             faces: HashMap::new(),
             keys: HashMap::new(),
+            cache: HashMap::new(),
+            fallbacks: HashMap::new(),
+            fallback_frozen: false,
             dpi_x: dpi_x as u32,
             dpi_y: dpi_y as u32,
             dpr: device_pixel_ratio,
+            options: GlyphOptions {
+                hollow_stroke: None,
+                bearing_overflow: BearingOverflow::Zero,
+                snap_origin: false,
+                sharpen: None,
+                row_alignment: 1,
+                padding: (0, [0; 3]),
+                gamma: None,
+                coverage_threshold: None,
+                hinting: Hinting::Off,
+                invert_coverage: false,
+                min_stem: false,
+            },
+            rendering_params: None,
+            metrics_scale: 1.,
+            subpixel_phases: 3,
+            tab_mode: TabMode::Fixed,
+            locale: None,
+            baseline_spacing: None,
+            lost: HashSet::new(),
         })
     }
 
-    fn metrics(&self, key: FontKey, size: Size) -> Result<Metrics, Error> {
+    /// Get the collection fonts are loaded from, for sharing with other rasterizers
+    ///
+    /// It's reference counted rather than atomically, so it can only be shared on this thread.
+    pub fn collection(&self) -> Rc<FontCollection> {
+        self.library.clone()
+    }
+
+    /// Scale the cell metrics reported by `metrics` by `scale`
+    ///
+    /// This is for zooming the grid, e.g. for accessibility; it's applied on top of the
+    /// device pixel ratio and doesn't affect rasterized glyphs.
+    pub fn set_metrics_scale(&mut self, scale: f32) {
+        self.metrics_scale = scale;
+    }
+
+    /// Override the distance between baselines reported as `line_height` by `metrics`, in pixels
+    ///
+    /// Unlike `set_metrics_scale`, this leaves the cell width alone, for double-spaced or
+    /// compact lines. Zero or less goes back to the font's own line height.
+    pub fn set_baseline_spacing(&mut self, px: f32) {
+        self.baseline_spacing = if px > 0. { Some(px) } else { None };
+    }
+
+    /// Get the number of columns and rows of cells that fit in a `viewport` of pixels
+    pub fn grid_dimensions(&self, key: FontKey, size: Size, viewport: (u32, u32)) -> Result<(u32, u32), Error> {
+        let metrics = self.metrics(key, size)?;
+
+        let columns = (viewport.0 as f64 / metrics.average_advance).floor() as u32;
+        let rows = (viewport.1 as f64 / metrics.line_height).floor() as u32;
+        Ok((columns, rows))
+    }
+
+    /// Get `Metrics` for the given `FontKey` and `Size` as measured by `mode`
+    ///
+    /// GDI measuring modes use the hinted metrics GDI itself would report, and GDI classic snaps
+    /// advances to whole pixels, so glyphs rendered with those modes should be laid out with
+    /// metrics from the same mode.
+    pub fn metrics_with_mode(
+        &self,
+        key: FontKey,
+        size: Size,
+        mode: DWRITE_MEASURING_MODE
+    ) -> Result<Metrics, Error> {
+        self.scaled_metrics(key, size, mode, self.metrics_scale)
+    }
+
+    /// Get `Metrics` as measured by `mode`, for a grid zoomed by `scale`
+    fn scaled_metrics(
+        &self,
+        key: FontKey,
+        size: Size,
+        mode: DWRITE_MEASURING_MODE,
+        scale: f32
+    ) -> Result<Metrics, Error> {
         let face = self.faces
             .get(&key)
             .ok_or(Error::FontNotLoaded)?;
+        if self.lost.contains(&key) {
+            return Err(Error::FontUnavailable(self.fonts[&key].family_name()));
+        }
 
-        let dm = face.metrics();
-        // I can't find an "average" metric, so this is hack that just gets the metrics for 'A'
-        let a_index = face.get_glyph_indices(&['A' as u32])[0];
-        let gm = face.get_design_glyph_metrics(&[a_index], false)[0];
+        let scale_size = self.dpr as f64 * size.as_f32_pts() as f64 * scale as f64;
 
-        let scale_size = self.dpr as f64 * size.as_f32_pts() as f64;
+        // I can't find an "average" metric, so this is hack that just gets the metrics for 'A'
+        let (dm, gm) = platform_call("IDWriteFontFace::GetDesignGlyphMetrics", AssertUnwindSafe(|| {
+            let a_index = face.get_glyph_indices(&['A' as u32])[0];
+            if mode == DWRITE_MEASURING_MODE_NATURAL {
+                Ok((face.metrics(), face.get_design_glyph_metrics(&[a_index], false)[0]))
+            } else {
+                let use_gdi_natural = mode != DWRITE_MEASURING_MODE_GDI_CLASSIC;
+                Ok((gdi_font_metrics(face, scale_size as f32)?,
+                    gdi_glyph_metrics(face, a_index, scale_size as f32, use_gdi_natural)?))
+            }
+        })).and_then(|metrics| metrics).map_err(|err| self.font_error(key, err))?;
+        let gm = clamp_advance(gm, 'A');
 
         let em_size = dm.designUnitsPerEm as f64;
         let w = gm.advanceWidth as f64;
         let h = (dm.ascent - dm.descent + dm.capHeight) as f64;
 
-        let w_scale = w * scale_size / em_size;
-        let h_scale = h * scale_size / em_size;
+        let mut w_scale = w * scale_size / em_size;
+        let mut h_scale = h * scale_size / em_size;
+
+        // GDI-compatible metrics already scale to whole pixels, this only drops the error from
+        // scaling them. GDI natural keeps its fractional advances, but not its line heights.
+        if mode != DWRITE_MEASURING_MODE_NATURAL {
+            if mode == DWRITE_MEASURING_MODE_GDI_CLASSIC {
+                w_scale = w_scale.round();
+            }
+            h_scale = h_scale.round();
+        }
+
+        if let Some(spacing) = self.baseline_spacing {
+            h_scale = spacing as f64;
+        }
 
         Ok(Metrics {
             average_advance: w_scale,
@@ -70,151 +946,2399 @@ impl ::Rasterize for DwroteRasterizer {
         })
     }
 
-    fn load_font(&mut self, desc: &FontDesc, _size: Size) -> Result<FontKey, Error> {
-        self.keys
-            .get(&desc.to_owned())
-            .map(|k| Ok(*k))
-            .unwrap_or_else(|| {
-                let face = self.get_face(desc)?;
-                let key = FontKey::next();
-                self.faces.insert(key, face);
-                Ok(key)
-            })
-    }
-
-    fn get_glyph(&mut self, glyph_key: &GlyphKey) -> Result<RasterizedGlyph, Error> {
+    /// Get the raw design metrics DirectWrite reports for `c` in the font at `key`
+    ///
+    /// Values are in font design units; scale by `size / designUnitsPerEm` to get pixels. This
+    /// never consults fallback fonts, since their design units can differ.
+    pub fn raw_glyph_metrics(&self, key: FontKey, c: char) -> Result<GlyphMetrics, Error> {
         let face = self.faces
-            .get(&glyph_key.font_key)
+            .get(&key)
             .ok_or(Error::FontNotLoaded)?;
 
-        let size = glyph_key.size.as_f32_pts() * self.dpr;
-        let c = glyph_key.c;
-        let c_index = face.get_glyph_indices(&[c as u32])[0];
-        let gm = face.get_design_glyph_metrics(&[c_index], false)[0];
+        Ok(design_glyph_metrics(face, c))
+    }
 
-        let design_units_per_pixel = face.metrics().designUnitsPerEm as f32 / 16. as f32;
-        let scaled_design_units_to_pixels = size / design_units_per_pixel;
+    /// Get the numeric weight, width class and slant of the face resolved for `key`
+    ///
+    /// Useful for diagnosing why a font description matched an unexpected face.
+    pub fn resolved_style_numeric(&self, key: FontKey) -> Result<NumericStyle, Error> {
+        let font = self.fonts
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
 
-        let width = (gm.advanceWidth as i32 - (gm.leftSideBearing + gm.rightSideBearing)) as f32 * scaled_design_units_to_pixels;
-        let height = (gm.advanceHeight as i32 - (gm.topSideBearing + gm.bottomSideBearing)) as f32 * scaled_design_units_to_pixels;
-        let x = (-gm.leftSideBearing) as f32 * scaled_design_units_to_pixels;
-        let y = (gm.verticalOriginY - gm.topSideBearing) as f32 * scaled_design_units_to_pixels;
+        let slant = match font.style() {
+            FontStyle::Normal => 0.,
+            FontStyle::Italic | FontStyle::Oblique => slanted_angle(&self.faces[&key]),
+        };
 
-        let gdi_interop = GdiInterop::create();
-        let rt = gdi_interop.create_bitmap_render_target(width as u32, height as u32);
-        let rp = RenderingParams::create_for_primary_monitor();
-        rt.set_pixels_per_dip(self.dpr);
-        //let em_size = 10.0f32; // pulled this value from dwrite, but I'm not sure if it's correct. It's kind of a magic number...
-        rt.draw_glyph_run(x as f32, y as f32,
-                          DWRITE_MEASURING_MODE_NATURAL,
-                          &face,
-                          size,
-                          &[c_index],
-                          &[0f32],
-                          &[GlyphOffset { advanceOffset: 0., ascenderOffset: 0. }],
-                          &rp,
-                          &(255.0f32, 255.0f32, 255.0f32));
-        let bytes = rt.get_opaque_values_as_mask();
-
-        Ok(RasterizedGlyph {
-            c: c,
-            top: y as i32,
-            left: x as i32,
-            width: width as i32,
-            height: width as i32,
-            buf: bytes,
+        Ok(NumericStyle {
+            weight: font.weight().to_u32(),
+            stretch: font.stretch().to_u32(),
+            slant: slant,
         })
     }
-}
 
-impl DwroteRasterizer {
-    /// Load a font face accoring to `FontDesc`
-    fn get_face(&mut self, desc: &FontDesc) -> Result<FontFace, Error> {
-        match desc.style {
-            Style::Description { slant, weight } => {
-                // Match nearest font
-                self.get_matching_face(&desc, slant, weight)
-            }
-            Style::Specific(ref style) => {
-                // If a name was specified, try and load specifically that font.
-                self.get_specific_face(&desc, &style)
-            }
-        }
+    /// Rasterize the glyph described by `GlyphKey` slanted as a synthesized italic
+    ///
+    /// For families without an italic face. The lean matches the angle declared by the
+    /// closest italic face in the family if there is one, so synthesized glyphs sit well next
+    /// to real italics from the same family.
+    pub fn get_glyph_synthetic_italic(&mut self, glyph_key: &GlyphKey) -> Result<RasterizedGlyph, Error> {
+        let key = self.resolve_font(glyph_key.font_key, glyph_key.c)?;
+        let shear = italic_shear(self.synthetic_italic_angle(key));
+        self.get_glyph_transformed(glyph_key, &shear_matrix(shear))
     }
 
-    fn get_matching_face(
+    /// Rasterize the glyph described by `GlyphKey` with `transform` applied around the pen
+    ///
+    /// This combines scaling, shearing and rotation in a single pass. The bitmap is sized to
+    /// fit the transformed glyph, and `top` and `left` are the pen position within it, so the
+    /// glyph is placed the same way as untransformed ones.
+    pub fn get_glyph_transformed(
         &mut self,
-        desc: &FontDesc,
-        slant: Slant,
-        weight: Weight
-    ) -> Result<FontFace, Error> {
-        let family = self.library.get_font_family_by_name(&desc.name).unwrap();
-        //map slant to FontStyle and weight to FontWeight
-        let font_style = match slant {
-            Slant::Normal   => FontStyle::Normal,
-            Slant::Italic   => FontStyle::Italic,
-            Slant::Oblique  => FontStyle::Oblique
+        glyph_key: &GlyphKey,
+        transform: &Transform
+    ) -> Result<RasterizedGlyph, Error> {
+        if is_invisible(glyph_key.c) {
+            return Ok(empty_glyph(glyph_key.c));
+        }
+
+        let size = glyph_key.size.as_f32_pts() * self.dpr;
+        let mut glyph = self.rasterize_resolved(glyph_key.font_key, glyph_key.c, |rasterizer, face| {
+            let index = glyph_index(face, glyph_key.c)?;
+            rasterizer.rasterize_index_transformed(face, glyph_key.c, index, size, 0., Some(transform))
+        })?;
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+        Ok(glyph)
+    }
+
+    /// Rasterize `c` at a weight between the font's named weights
+    ///
+    /// Setting the `wght` axis of a variable font takes `IDWriteFontFace5` and
+    /// `IDWriteFontResource`, which neither dwrote nor winapi bind. DirectWrite does list a
+    /// variable font's named instances as faces of its family, so this interpolates coverage
+    /// between the nearest lighter and heavier faces instead, for static and variable families
+    /// alike. `weight` uses the usual 100-900 scale. Fails with `Error::MissingFont` if the
+    /// family can no longer be found in the system collection.
+    pub fn get_glyph_weighted(
+        &mut self,
+        key: FontKey,
+        c: char,
+        size: Size,
+        weight: f32
+    ) -> Result<RasterizedGlyph, Error> {
+        let font = self.fonts
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
+
+        let weight = weight.max(100.).min(900.);
+        let lighter = (weight / 100.).floor() * 100.;
+        let heavier = (weight / 100.).ceil() * 100.;
+
+        let family = self.library
+            .get_font_family_by_name(&font.family_name())
+            .ok_or_else(|| Error::MissingFont(self.font_desc(key)))?;
+        let face_for = |w: f32| {
+            let font = family.get_first_matching_font(FontWeight::from_u32(w as u32), font.stretch(), font.style());
+            platform_call("IDWriteFont::CreateFontFace", AssertUnwindSafe(|| font.create_font_face()))
         };
-        let font_weight = match weight {
-            Weight::Normal  => FontWeight::Regular,
-            Weight::Bold    => FontWeight::Bold,
+
+        let size = size.as_f32_pts() * self.dpr;
+        let mut glyph = self.rasterize_from(key, c, |rasterizer, _| {
+            let light_glyph = rasterizer.rasterize_glyph(&face_for(lighter)?, c, size)?;
+            if heavier == lighter {
+                return Ok(light_glyph);
+            }
+
+            let heavy_glyph = rasterizer.rasterize_glyph(&face_for(heavier)?, c, size)?;
+            let t = (weight - lighter) / (heavier - lighter);
+            Ok(bitmap::blend(&light_glyph, &heavy_glyph, t))
+        })?;
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+        Ok(glyph)
+    }
+
+    /// Describe the font at `key` for reporting errors about it
+    ///
+    /// This is the description the font was loaded from, or one made up from the font itself
+    /// for fallback fonts.
+    fn font_desc(&self, key: FontKey) -> FontDesc {
+        if let Some((desc, _)) = self.keys.iter().find(|&(_, &loaded)| loaded == key) {
+            return desc.clone();
+        }
+
+        let font = &self.fonts[&key];
+        let weight = if font.weight().to_u32() >= 700 { Weight::Bold } else { Weight::Normal };
+        let slant = match font.style() {
+            FontStyle::Normal => Slant::Normal,
+            FontStyle::Italic => Slant::Italic,
+            FontStyle::Oblique => Slant::Oblique,
+        };
+        FontDesc::new(font.family_name(), Style::Description { slant: slant, weight: weight })
+    }
+
+    /// Export per-glyph metrics for `chars` as a JSON array, scaled to pixels at `size`
+    ///
+    /// Intended for diagnosing spacing issues with external tools.
+    pub fn export_metrics(&self, key: FontKey, chars: &[char], size: Size) -> Result<String, Error> {
+        let face = self.faces
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
+
+        let scale = self.pixels_per_design_unit(face, size);
+        let indices = face.get_glyph_indices(&chars.iter().map(|&c| c as u32).collect::<Vec<_>>());
+        let metrics = face.get_design_glyph_metrics(&indices, false);
+        let em = face.metrics().designUnitsPerEm;
+        let vertical_metrics = has_vertical_metrics(face);
+
+        let entries = chars.iter().zip(metrics.into_iter()).map(|(&c, gm)| {
+            let gm = &with_horizontal_advance(gm, em, vertical_metrics, c);
+            let width = ink_width(gm, self.options.bearing_overflow);
+            let height = gm.advanceHeight as i32 - (gm.topSideBearing + gm.bottomSideBearing);
+            format!("{{\"char\":\"{}\",\"codepoint\":{},\"advance\":{},\
+                     \"left_bearing\":{},\"right_bearing\":{},\
+                     \"top_bearing\":{},\"bottom_bearing\":{},\
+                     \"width\":{},\"height\":{}}}",
+                    json_escape(c),
+                    c as u32,
+                    gm.advanceWidth as f32 * scale,
+                    gm.leftSideBearing as f32 * scale,
+                    gm.rightSideBearing as f32 * scale,
+                    gm.topSideBearing as f32 * scale,
+                    gm.bottomSideBearing as f32 * scale,
+                    width as f32 * scale,
+                    height as f32 * scale)
+        }).collect::<Vec<_>>();
+
+        Ok(format!("[{}]", entries.join(",")))
+    }
+
+    /// Rasterize the glyph described by `GlyphKey`, reusing a previous result when possible
+    ///
+    /// The returned flag is true when the glyph was served from the cache. Changing any
+    /// rendering option empties the cache.
+    pub fn get_glyph_cached(&mut self, glyph_key: &GlyphKey) -> Result<(RasterizedGlyph, bool), Error> {
+        if let Some(glyph) = self.cache.get(&(glyph_key.clone(), None)) {
+            return Ok((glyph.clone(), true));
+        }
+
+        let glyph = self.get_glyph(glyph_key)?;
+        self.cache.insert((glyph_key.clone(), None), glyph.clone());
+        Ok((glyph, false))
+    }
+
+    /// Rasterize the glyph described by `GlyphKey` for a pen position with fractional part
+    /// `x_offset`
+    ///
+    /// Offsets are rounded down to one of `set_subpixel_phases` phases, and each phase is
+    /// cached separately.
+    pub fn get_glyph_subpixel(&mut self, glyph_key: &GlyphKey, x_offset: f32) -> Result<RasterizedGlyph, Error> {
+        let phase = subpixel_phase(x_offset, self.subpixel_phases);
+        if let Some(glyph) = self.cache.get(&(glyph_key.clone(), Some(phase))) {
+            return Ok(glyph.clone());
+        }
+
+        let size = glyph_key.size.as_f32_pts() * self.dpr;
+        let offset = phase as f32 / self.subpixel_phases as f32;
+
+        let mut glyph = if is_invisible(glyph_key.c) {
+            empty_glyph(glyph_key.c)
+        } else {
+            self.rasterize_resolved(glyph_key.font_key, glyph_key.c, |rasterizer, face| {
+                let index = glyph_index(face, glyph_key.c)?;
+                rasterizer.rasterize_index_at(face, glyph_key.c, index, size, offset)
+            })?
         };
-        // I want to use panic::catch_unwind, but dwrote does not support it
-        Ok(family.get_first_matching_font(font_weight, FontStretch::Normal, font_style).create_font_face())
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+
+        self.cache.insert((glyph_key.clone(), Some(phase)), glyph.clone());
+        Ok(glyph)
+    }
+
+    /// Set how many horizontal subpixel positions `get_glyph_subpixel` distinguishes
+    ///
+    /// More phases give smoother positioning at the cost of caching more glyphs. Values are
+    /// clamped to between 1, which disables subpixel positioning, and 4.
+    pub fn set_subpixel_phases(&mut self, phases: u8) {
+        self.subpixel_phases = cmp::min(cmp::max(phases, 1), 4);
+        self.cache.clear();
+    }
+
+    /// Rasterize every glyph in the font at `key`, in glyph index order
+    ///
+    /// Useful for pre-generating atlases or analysing a whole face.
+    pub fn iterate_glyphs(&self, key: FontKey, size: Size) -> Result<Glyphs, Error> {
+        let face = self.faces
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
+        if self.lost.contains(&key) {
+            return Err(Error::FontUnavailable(self.fonts[&key].family_name()));
+        }
+
+        Ok(Glyphs {
+            rasterizer: self,
+            key: key,
+            face: face,
+            size: size.as_f32_pts() * self.dpr,
+            next: 0,
+            count: face.get_glyph_count(),
+        })
+    }
+
+    /// Get underline placement for the font at `key` scaled to `size`
+    pub fn underline_metrics(&self, key: FontKey, size: Size) -> Result<UnderlineMetrics, Error> {
+        let face = self.faces
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
+
+        let dm = face.metrics();
+        let scale = self.pixels_per_design_unit(face, size);
+        let (position, thickness) = underline_design_units(&dm);
+
+        Ok(UnderlineMetrics {
+            position: position as f32 * scale,
+            thickness: thickness as f32 * scale,
+        })
     }
 
-    fn get_specific_face(
+    /// Rasterize the combining `mark` positioned over or under `base`
+    ///
+    /// The rasterizer doesn't shape text, so GPOS anchors aren't consulted and marks are placed
+    /// heuristically. `top` and `left` of the result are relative to the pen origin of `base`.
+    pub fn get_mark_glyph(
         &mut self,
-        desc: &FontDesc,
-        style: &str
-    ) -> Result<FontFace, Error> {
-        let family = self.library.get_font_family_by_name(&desc.name).unwrap();
-        // parse style into either Normal, Bold, or Italic
-        // I guess this is how specific face is supposed to work? idk for sure...
-        let font_info = match style {
-            "Normal"    => (FontWeight::Regular, FontStretch::Normal, FontStyle::Normal),
-            "Bold"      => (FontWeight::Bold, FontStretch::Normal, FontStyle::Normal),
-            "Italic"    => (FontWeight::Regular, FontStretch::Normal, FontStyle::Italic),
-            &_          => (FontWeight::Regular, FontStretch::Normal, FontStyle::Normal),
+        key: FontKey,
+        base: char,
+        mark: char,
+        size: Size
+    ) -> Result<RasterizedGlyph, Error> {
+        let base_glyph = self.get_glyph(&GlyphKey { c: base, font_key: key, size: size })?;
+        let mut mark_glyph = self.get_glyph(&GlyphKey { c: mark, font_key: key, size: size })?;
+
+        let face = &self.faces[&key];
+        let x_height = face.metrics().xHeight as f32 * self.pixels_per_design_unit(face, size);
+
+        place_mark(&base_glyph, &mut mark_glyph, x_height.round() as i32);
+        Ok(mark_glyph)
+    }
+
+    /// Rasterize each of `chars`, returning glyphs paired with their character in input order
+    ///
+    /// Characters the font at `key` can't render are drawn from a fallback font; use
+    /// `fallback_font` to find out which ones were.
+    pub fn get_glyph_run(
+        &mut self,
+        key: FontKey,
+        chars: &[char],
+        size: Size
+    ) -> Result<Vec<(char, RasterizedGlyph)>, Error> {
+        chars.iter()
+            .map(|&c| {
+                let glyph = self.get_glyph(&GlyphKey { c: c, font_key: key, size: size })?;
+                Ok((c, glyph))
+            })
+            .collect()
+    }
+
+    /// Rasterize the glyphs described by `keys` on several threads, returning results in order
+    ///
+    /// Meant for filling a glyph atlas up front. The results are identical to `get_glyph`,
+    /// except that with only shared access the rasterizer can't look up new fallback fonts:
+    /// characters the fonts lack are drawn from fallbacks already found by `get_glyph`, or as
+    /// the font's missing glyph box. Glyphs aren't cached.
+    pub fn rasterize_batch_parallel(&self, keys: &[GlyphKey]) -> Vec<Result<RasterizedGlyph, Error>> {
+        let chunk_size = (keys.len() + BATCH_THREADS - 1) / BATCH_THREADS;
+        if chunk_size == 0 {
+            return Vec::new();
+        }
+
+        // Fonts are resolved here, so only plain values are handed to the workers
+        let jobs = keys.iter().map(|glyph_key| self.batch_job(glyph_key)).collect::<Vec<_>>();
+        let sources = jobs.iter()
+            .filter_map(|job| job.as_ref().ok())
+            .map(|&(key, _)| (key, FontSource::of(&self.fonts[&key])))
+            .collect::<HashMap<_, _>>();
+        let (dpi_x, dpi_y, dpr, options) = (self.dpi_x as f32, self.dpi_y as f32, self.dpr, self.options);
+
+        let glyphs = crossbeam::scope(|scope| {
+            let handles = jobs.chunks(chunk_size)
+                .map(|chunk| {
+                    let sources = &sources;
+                    scope.spawn(move || {
+                        DwroteRasterizer::rasterize_batch_chunk(chunk, sources, dpi_x, dpi_y, dpr, options)
+                    })
+                })
+                .collect::<Vec<_>>();
+
+            handles.into_iter().flat_map(|handle| handle.join()).collect::<Vec<_>>()
+        });
+
+        // Workers leave glyphs from fonts they couldn't load again to this thread
+        glyphs.into_iter()
+            .zip(jobs.into_iter())
+            .map(|(glyph, job)| glyph.unwrap_or_else(|| {
+                job.and_then(|(key, glyph_key)| self.rasterize_batch_glyph(key, &glyph_key))
+            }))
+            .collect()
+    }
+
+    /// Find the font `rasterize_batch_parallel` draws the glyph for `glyph_key` from
+    fn batch_job(&self, glyph_key: &GlyphKey) -> Result<(FontKey, GlyphKey), Error> {
+        if !self.faces.contains_key(&glyph_key.font_key) {
+            return Err(Error::FontNotLoaded);
+        }
+
+        let key = self.fallback_font(glyph_key.font_key, glyph_key.c).unwrap_or(glyph_key.font_key);
+        Ok((key, glyph_key.clone()))
+    }
+
+    /// Rasterize the glyphs for `jobs` on a `rasterize_batch_parallel` worker thread
+    ///
+    /// The worker shares no COM objects with the thread that started the batch. It has a
+    /// rasterizer of its own, with its own collection and GDI interop, and loads the fonts it
+    /// needs from `sources` under the same keys. Glyphs from fonts it can't find that way,
+    /// like ones from a fallback collection, are left as `None` for the caller to draw.
+    fn rasterize_batch_chunk(
+        jobs: &[Result<(FontKey, GlyphKey), Error>],
+        sources: &HashMap<FontKey, FontSource>,
+        dpi_x: f32,
+        dpi_y: f32,
+        dpr: f32,
+        options: GlyphOptions
+    ) -> Vec<Option<Result<RasterizedGlyph, Error>>> {
+        let mut rasterizer = match DwroteRasterizer::new(dpi_x, dpi_y, dpr, false) {
+            Ok(rasterizer) => rasterizer,
+            Err(_) => return jobs.iter().map(|_| None).collect(),
+        };
+        rasterizer.options = options;
+        // The rendering params are COM objects too, so workers create their own
+        if let Some(gamma) = options.gamma {
+            if rasterizer.set_gamma(gamma).is_err() {
+                return jobs.iter().map(|_| None).collect();
+            }
+        }
+
+        jobs.iter()
+            .map(|job| {
+                let (key, ref glyph_key) = match *job {
+                    Ok(ref job) => job.clone(),
+                    Err(ref err) => return Some(Err(err.clone())),
+                };
+
+                if !rasterizer.faces.contains_key(&key) && !rasterizer.load_source(key, &sources[&key]) {
+                    return None;
+                }
+
+                Some(rasterizer.rasterize_batch_glyph(key, glyph_key))
+            })
+            .collect()
+    }
+
+    /// Load the font described by `source` under `key`, returning whether it was found
+    ///
+    /// A font is only accepted if it has exactly the family and style asked for.
+    fn load_source(&mut self, key: FontKey, source: &FontSource) -> bool {
+        let font = match self.library.get_font_family_by_name(&source.family) {
+            // Matching in an empty family panics inside dwrote
+            Some(ref family) if family.get_font_count() > 0 => {
+                family.get_first_matching_font(FontWeight::from_u32(source.weight),
+                                               FontStretch::from_u32(source.stretch),
+                                               FontStyle::from_u32(source.style))
+            },
+            _ => return false,
         };
-        // I want to use panic::catch_unwind, but dwrote does not support it.
-        Ok(family.get_first_matching_font(font_info.0, font_info.1, font_info.2).create_font_face())
+
+        if FontSource::of(&font) != *source {
+            return false;
+        }
+
+        let face = match platform_call("IDWriteFont::CreateFontFace", AssertUnwindSafe(|| font.create_font_face())) {
+            Ok(face) => face,
+            Err(_) => return false,
+        };
+
+        self.faces.insert(key, face);
+        self.fonts.insert(key, font);
+        true
     }
-}
 
-/// Errors occurring when using the directwrite rasterizer
-#[derive(Debug)]
-pub enum Error {
-    /// Couldn't find font matching description
-    MissingFont(FontDesc),
+    /// Rasterize the glyph for `glyph_key` from the font at `key`, as it's drawn in a batch
+    fn rasterize_batch_glyph(&self, key: FontKey, glyph_key: &GlyphKey) -> Result<RasterizedGlyph, Error> {
+        let size = glyph_key.size.as_f32_pts() * self.dpr;
+        let mut glyph = self.rasterize_from(key, glyph_key.c, |rasterizer, face| {
+            rasterizer.rasterize_glyph(face, glyph_key.c, size)
+        })?;
 
-    /// Requested an operation with a FontKey that isn't known to the rasterizer
-    FontNotLoaded,
-}
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+        Ok(glyph)
+    }
+
+    /// Check whether `a` and `b` rasterize to identical bitmaps
+    ///
+    /// Renderers can use this to compress runs of cells that look the same.
+    pub fn render_equal(&mut self, key: FontKey, a: char, b: char, size: Size) -> Result<bool, Error> {
+        let a = self.get_glyph(&GlyphKey { c: a, font_key: key, size: size })?;
+        let b = self.get_glyph(&GlyphKey { c: b, font_key: key, size: size })?;
+
+        Ok(a.width == b.width && a.height == b.height && a.top == b.top && a.left == b.left &&
+           a.buf == b.buf)
+    }
+
+    /// Rasterize a zero width joiner sequence such as a family emoji
+    ///
+    /// Joining a sequence into a single glyph requires shaping, which the rasterizer doesn't
+    /// do. Rather than drawing one missing glyph box, each component is rendered separately,
+    /// with font fallback, in sequence order.
+    pub fn get_zwj_sequence(
+        &mut self,
+        key: FontKey,
+        sequence: &str,
+        size: Size
+    ) -> Result<Vec<RasterizedGlyph>, Error> {
+        zwj_components(sequence).into_iter()
+            .map(|c| self.get_glyph(&GlyphKey { c: c, font_key: key, size: size }))
+            .collect()
+    }
+
+    /// Rasterize the glyph described by `GlyphKey` with its buffer in `color_space`
+    pub fn get_glyph_in_color_space(
+        &mut self,
+        glyph_key: &GlyphKey,
+        color_space: ColorSpace
+    ) -> Result<ColorGlyph, Error> {
+        let mut glyph = self.get_glyph(glyph_key)?;
+        if color_space == ColorSpace::Linear {
+            bitmap::srgb_to_linear(&mut glyph);
+        }
+
+        Ok(ColorGlyph {
+            glyph: glyph,
+            color_space: color_space,
+        })
+    }
+
+    /// Rasterize the glyph described by `GlyphKey` in color, from its font's `COLR` layers
+    ///
+    /// GDI interop only draws coverage, so each layer is rasterized on its own and tinted with
+    /// its color from the first `CPAL` palette; layers drawn in the text color use
+    /// `foreground`. The buffer holds premultiplied RGBA pixels, four bytes each, and rows
+    /// aren't padded. Glyphs without color layers fail with `Error::MissingTable`.
+    pub fn get_glyph_color(&mut self, glyph_key: &GlyphKey, foreground: [u8; 3]) -> Result<RasterizedGlyph, Error> {
+        let c = glyph_key.c;
+        let size = glyph_key.size.as_f32_pts() * self.dpr;
+
+        self.rasterize_resolved(glyph_key.font_key, c, |rasterizer, face| {
+            let (colr, cpal) = platform_call("IDWriteFontFace::TryGetFontTable", AssertUnwindSafe(|| {
+                (face.get_font_table(opentype_tag(b"COLR")), face.get_font_table(opentype_tag(b"CPAL")))
+            }))?;
+            let colr = colr.ok_or(Error::MissingTable("COLR"))?;
+            let cpal = cpal.ok_or(Error::MissingTable("CPAL"))?;
+
+            let index = glyph_index(face, c)?;
+            let layers = tables::color_layers(&colr, index).ok_or(Error::MissingTable("COLR"))?;
+
+            let mut tinted = Vec::with_capacity(layers.len());
+            for (layer, entry) in layers {
+                let color = if entry == tables::FOREGROUND_PALETTE_ENTRY {
+                    [foreground[0], foreground[1], foreground[2], 255]
+                } else {
+                    tables::palette_color(&cpal, 0, entry).ok_or(Error::MissingTable("CPAL"))?
+                };
+                tinted.push((rasterizer.rasterize_index(face, c, layer, size)?, color));
+            }
+
+            Ok(bitmap::composite(c, &tinted))
+        })
+    }
+
+    /// Get a description of the system's emoji font, if it's installed
+    ///
+    /// This is Segoe UI Emoji, which Windows 8.1 and later ship. `get_glyph` draws its glyphs
+    /// as coverage like any other font's; use `get_glyph_color` for their colors.
+    pub fn default_emoji_font(&self) -> Option<FontDesc> {
+        let desc = FontDesc::new(EMOJI_FAMILY, Style::Description {
+            slant: Slant::Normal,
+            weight: Weight::Normal,
+        });
+
+        self.get_family(&desc).ok().map(|_| desc)
+    }
+
+    /// Get the fallback font used to render `c` in place of the font at `key`, if any
+    pub fn fallback_font(&self, key: FontKey, c: char) -> Option<FontKey> {
+        self.fallbacks.get(&(key, c)).cloned()
+    }
+
+    /// Find the font to render `c` with, falling back to other system fonts when `key` lacks it
+    ///
+    /// When no installed font has `c`, `key` is returned so its missing glyph box is drawn.
+    fn resolve_font(&mut self, key: FontKey, c: char) -> Result<FontKey, Error> {
+        let (weight, stretch, style) = {
+            let font = self.fonts
+                .get(&key)
+                .ok_or(Error::FontNotLoaded)?;
+
+            if is_invisible(c) || (!self.lost.contains(&key) && font.has_character(c as u32)) {
+                return Ok(key);
+            }
+
+            (font.weight(), font.stretch(), font.style())
+        };
+
+        if let Some(&fallback) = self.fallbacks.get(&(key, c)) {
+            return Ok(fallback);
+        }
+
+        if self.fallback_frozen {
+            return Ok(key);
+        }
+
+        // The system collection isn't refreshed, so it still lists fonts that have gone away
+        let lost = self.lost.iter()
+            .map(|lost| self.fonts[lost].family_name())
+            .collect::<Vec<_>>();
+
+        if is_han(c) {
+            if let Some(fallback) = self.locale_fallback(key, c, weight, stretch, style, &lost) {
+                return Ok(fallback);
+            }
+        }
+
+        // Prefer fallback fonts that are already loaded
+        if let Some(fallback) = self.loaded_fallback(c, weight, stretch, style, None) {
+            self.fallbacks.insert((key, c), fallback);
+            return Ok(fallback);
+        }
+
+        let font = self.fallback_sources()
+            .into_iter()
+            .filter_map(|collection| find_font_with_char(collection, c, weight, stretch, style, &lost))
+            .next();
+
+        match font {
+            Some(font) => Ok(self.add_fallback(key, c, font)),
+            None => Ok(key),
+        }
+    }
+
+    /// Find a fallback font that's already loaded and has `c`, optionally from `family`
+    ///
+    /// Only fallbacks found for fonts of the same weight, stretch and style are reused. They're
+    /// tried in the order they were loaded, so the choice doesn't depend on hashing.
+    fn loaded_fallback(
+        &self,
+        c: char,
+        weight: FontWeight,
+        stretch: FontStretch,
+        style: FontStyle,
+        family: Option<&str>
+    ) -> Option<FontKey> {
+        let mut loaded = self.fallbacks
+            .iter()
+            .filter(|&(&(key, _), _)| {
+                let font = &self.fonts[&key];
+                font.weight().to_u32() == weight.to_u32() &&
+                    font.stretch().to_u32() == stretch.to_u32() &&
+                    font.style().to_u32() == style.to_u32()
+            })
+            .map(|(_, &fallback)| fallback)
+            .collect::<Vec<_>>();
+        loaded.sort();
+        loaded.dedup();
+
+        loaded.into_iter().find(|fallback| {
+            let font = &self.fonts[fallback];
+            family.map_or(true, |family| font.family_name() == family) && font.has_character(c as u32)
+        })
+    }
+
+    /// Find a font for the Han ideograph `c` with the forms used in the configured locale
+    fn locale_fallback(
+        &mut self,
+        key: FontKey,
+        c: char,
+        weight: FontWeight,
+        stretch: FontStretch,
+        style: FontStyle,
+        excluded: &[String]
+    ) -> Option<FontKey> {
+        let families = match self.locale {
+            Some(ref locale) => locale_families(locale),
+            None => return None,
+        };
+
+        for name in families.iter().filter(|name| !excluded.iter().any(|lost| lost == *name)) {
+            if let Some(fallback) = self.loaded_fallback(c, weight, stretch, style, Some(name)) {
+                self.fallbacks.insert((key, c), fallback);
+                return Some(fallback);
+            }
+
+            let font = match self.library.get_font_family_by_name(name) {
+                Some(ref family) if family.get_font_count() != 0 => {
+                    family.get_first_matching_font(weight, stretch, style)
+                },
+                _ => continue,
+            };
+            if font.has_character(c as u32) {
+                return Some(self.add_fallback(key, c, font));
+            }
+        }
 
-impl ::std::error::Error for Error {
-    fn cause(&self) -> Option<&::std::error::Error> {
         None
     }
 
-    fn description(&self) -> &str {
-        match *self {
-            Error::MissingFont(ref _desc) => "couldn't find the requested font",
-            Error::FontNotLoaded => "tried to operate on font that hasn't been loaded",
+    /// Rasterize `c` with `rasterize` from the font `c` resolves to in place of `key`
+    ///
+    /// If the resolved font's file turns out to have gone away, e.g. because the font was
+    /// uninstalled while it was loaded, the font is dropped and `c` is resolved again. When
+    /// nothing else can draw `c` the result is `Error::FontUnavailable`.
+    fn rasterize_resolved<F>(&mut self, key: FontKey, c: char, rasterize: F) -> Result<RasterizedGlyph, Error>
+        where F: Fn(&DwroteRasterizer, &FontFace) -> Result<RasterizedGlyph, Error>
+    {
+        let resolved = self.resolve_font(key, c)?;
+
+        match self.rasterize_from(resolved, c, &rasterize) {
+            Err(Error::FontUnavailable(_)) if !self.lost.contains(&resolved) => {
+                self.lose_font(resolved);
+                let resolved = self.resolve_font(key, c)?;
+                self.rasterize_from(resolved, c, &rasterize)
+            },
+            result => result,
         }
     }
-}
 
-impl ::std::fmt::Display for Error {
-    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
-        match *self {
-            Error::MissingFont(ref desc) => {
-                write!(f, "Couldn't find a font with {}\
-                       \n\tPlease check the font config in your alacritty.yml.", desc)
+    /// Rasterize `c` with `rasterize` from the font at `key`, without any fallback
+    ///
+    /// Fails with `Error::FontUnavailable` if the font's file has gone away.
+    fn rasterize_from<F>(&self, key: FontKey, c: char, rasterize: F) -> Result<RasterizedGlyph, Error>
+        where F: FnOnce(&DwroteRasterizer, &FontFace) -> Result<RasterizedGlyph, Error>
+    {
+        // Invisible characters don't need the font at all
+        if self.lost.contains(&key) && !is_invisible(c) {
+            return Err(Error::FontUnavailable(self.fonts[&key].family_name()));
+        }
+
+        rasterize(self, &self.faces[&key]).map_err(|err| self.font_error(key, err))
+    }
+
+    /// Report `err`, from reading the font at `key`, as `Error::FontUnavailable` if the font's
+    /// file has gone away
+    ///
+    /// Other failures, including ones with files that are still there, are passed on as they
+    /// are so a healthy font isn't dropped for them.
+    fn font_error(&self, key: FontKey, err: Error) -> Error {
+        match err {
+            Error::PlatformError(_) if self.font_file_lost(key) => {
+                Error::FontUnavailable(self.fonts[&key].family_name())
             },
-            Error::FontNotLoaded => {
-                f.write_str("Tried to use a font that hasn't been loaded")
+            err => err,
+        }
+    }
+
+    /// Check whether the file behind the font at `key` can no longer be read
+    ///
+    /// Creating a face makes DirectWrite open the font's file again, which fails once it's
+    /// been deleted.
+    fn font_file_lost(&self, key: FontKey) -> bool {
+        let font = &self.fonts[&key];
+        platform_call("IDWriteFont::CreateFontFace", AssertUnwindSafe(|| font.create_font_face())).is_err()
+    }
+
+    /// Stop drawing from the font at `key`, whose file can no longer be read
+    ///
+    /// Characters it was the fallback for are resolved again.
+    fn lose_font(&mut self, key: FontKey) {
+        self.lost.insert(key);
+
+        let stale = self.fallbacks
+            .iter()
+            .filter(|&(_, fallback)| *fallback == key)
+            .map(|(&stale, _)| stale)
+            .collect::<Vec<_>>();
+        for stale in stale {
+            self.fallbacks.remove(&stale);
+        }
+    }
+
+    /// Load `font` as the fallback for drawing `c` in place of the font at `key`
+    fn add_fallback(&mut self, key: FontKey, c: char, font: Font) -> FontKey {
+        let fallback = FontKey::next();
+        self.faces.insert(fallback, font.create_font_face());
+        self.fonts.insert(fallback, font);
+        self.fallbacks.insert((key, c), fallback);
+        fallback
+    }
+
+    /// Set the locale used to choose fallback fonts, as a BCP 47 name like `ja-JP`
+    ///
+    /// Chinese, Japanese and Korean draw many of the same Han ideographs differently, so the
+    /// locale decides which region's font they fall back to. dwrote has no text analysis or
+    /// font fallback APIs, so this is a preference list of the fonts Windows ships for each
+    /// region rather than DirectWrite's own locale handling. `None` clears the preference.
+    pub fn set_locale(&mut self, locale: Option<&str>) {
+        self.locale = locale.map(|locale| locale.to_owned());
+        if !self.fallback_frozen {
+            self.fallbacks.clear();
+            self.cache.clear();
+        }
+    }
+
+    /// Search `collection` for fallback fonts before the system fonts
+    ///
+    /// This allows bundling fonts, such as an emoji font, without installing them.
+    pub fn set_fallback_collection(&mut self, collection: FontCollection) {
+        self.fallback_collection = Some(collection);
+        if !self.fallback_frozen {
+            self.fallbacks.clear();
+            self.cache.clear();
+        }
+    }
+
+    /// Get the collections searched for new fallback fonts, in the order they're searched
+    fn fallback_sources(&self) -> Vec<&FontCollection> {
+        self.fallback_collection.iter().chain(Some(&*self.library)).collect()
+    }
+
+    /// Pin the fallback fonts chosen so far, for reproducible output such as screenshot tests
+    ///
+    /// Afterwards characters always resolve to the font they resolved to before, and
+    /// characters that haven't needed fallback yet are drawn by the primary font, regardless
+    /// of which fonts are installed or later fallback collections.
+    pub fn freeze_fallback(&mut self) {
+        self.fallback_frozen = true;
+    }
+
+    /// Get the horizontal advance of `c` in pixels
+    ///
+    /// Like the other per-glyph metrics, this is measured in the font `c` is rasterized with,
+    /// which is looked up first if `c` needs a fallback font.
+    pub fn glyph_advance(&mut self, key: FontKey, c: char, size: Size) -> Result<f32, Error> {
+        if is_invisible(c) {
+            return Ok(0.);
+        }
+
+        let resolved = self.metrics_font(key, c)?;
+        let face = &self.faces[&resolved];
+
+        let em = face.metrics().designUnitsPerEm;
+        let gm = with_horizontal_advance(design_glyph_metrics(face, c), em, has_vertical_metrics(face), c);
+        Ok(gm.advanceWidth as f32 * self.pixels_per_design_unit(face, size))
+    }
+
+    /// Get the horizontal advance of `c` snapped to one or two whole cells, in pixels
+    ///
+    /// Laying text out with these advances keeps every glyph in its column, even when the font
+    /// isn't quite monospaced.
+    pub fn grid_advance(&mut self, key: FontKey, c: char, size: Size) -> Result<f32, Error> {
+        // Glyph advances aren't zoomed, so they're counted in cells that aren't either
+        let advance = self.glyph_advance(key, c, size)?;
+        let cells = cells_for_advance(advance, self.unzoomed_cell_width(key, size)? as f32);
+
+        Ok(cells as f32 * self.metrics(key, size)?.average_advance as f32)
+    }
+
+    /// Set how wide tabs are; see `tab_advance`
+    pub fn set_tab_mode(&mut self, mode: TabMode) {
+        self.tab_mode = mode;
+    }
+
+    /// Get the advance of a tab typed at `current_col`, in pixels
+    ///
+    /// Tab stops are every 8 columns. All tabs are a full tab stop wide unless elastic tabs are
+    /// enabled with `set_tab_mode`, in which case they end at the following stop.
+    pub fn tab_advance(&self, key: FontKey, size: Size, current_col: u32) -> Result<f32, Error> {
+        let cell = self.metrics(key, size)?.average_advance as f32;
+        Ok(tab_cells(self.tab_mode, current_col) as f32 * cell)
+    }
+
+    /// Get the distance from the pen position to the left edge of the ink of `c`
+    ///
+    /// Beam cursors drawn at the visual start of a glyph need this rather than the pen position.
+    pub fn ink_left_bearing(&mut self, key: FontKey, c: char, size: Size) -> Result<f32, Error> {
+        let resolved = self.metrics_font(key, c)?;
+        let face = &self.faces[&resolved];
+
+        let gm = design_glyph_metrics(face, c);
+        Ok(gm.leftSideBearing as f32 * self.pixels_per_design_unit(face, size))
+    }
+
+    /// Check whether the ink of `c` is wider than a cell of the font at `key`
+    ///
+    /// Glyphs that overflow will be clipped or bleed into neighbouring cells.
+    pub fn glyph_overflows_cell(&mut self, key: FontKey, c: char, size: Size) -> Result<bool, Error> {
+        let resolved = self.metrics_font(key, c)?;
+        let face = &self.faces[&resolved];
+
+        let gm = with_horizontal_advance(design_glyph_metrics(face, c), face.metrics().designUnitsPerEm,
+                                         has_vertical_metrics(face), c);
+        let ink = ink_width(&gm, self.options.bearing_overflow) as f32 * self.pixels_per_design_unit(face, size);
+        let cell = self.unzoomed_cell_width(key, size)?;
+
+        Ok(ink as f64 > cell)
+    }
+
+    /// Get the width of a cell of the font at `key`, leaving out `set_metrics_scale`
+    ///
+    /// This is the scale glyphs are measured and rasterized at.
+    fn unzoomed_cell_width(&self, key: FontKey, size: Size) -> Result<f64, Error> {
+        Ok(self.scaled_metrics(key, size, DWRITE_MEASURING_MODE_NATURAL, 1.)?.average_advance)
+    }
+
+    /// Count the closed contours that make up the outline of `c`
+    ///
+    /// dwrote doesn't expose glyph outlines, so this reads the TrueType `glyf` table directly.
+    /// Fonts with CFF outlines fail with `Error::MissingTable`.
+    pub fn contour_count(&self, key: FontKey, c: char) -> Result<usize, Error> {
+        let face = self.faces
+            .get(&key)
+            .ok_or(Error::FontNotLoaded)?;
+
+        let table = |name: &'static [u8; 4]| {
+            face.get_font_table(opentype_tag(name))
+                .ok_or(Error::MissingTable(::std::str::from_utf8(name).unwrap()))
+        };
+        let head = table(b"head")?;
+        let loca = table(b"loca")?;
+        let glyf = table(b"glyf")?;
+
+        let long_offsets = tables::long_loca_offsets(&head).ok_or(Error::MissingTable("head"))?;
+        let index = face.get_glyph_indices(&[c as u32])[0];
+
+        tables::contour_count(&glyf, &loca, long_offsets, index).ok_or(Error::MissingTable("glyf"))
+    }
+
+    /// Rasterize `c` scaled so its bitmap is exactly `target_height_px` pixels tall
+    ///
+    /// Useful for fitting icons to a line. Any padding set with `set_padding` is added on top
+    /// of the target. Glyphs without ink come back empty.
+    pub fn get_glyph_fit_height(
+        &mut self,
+        key: FontKey,
+        c: char,
+        target_height_px: u32
+    ) -> Result<RasterizedGlyph, Error> {
+        let mut glyph = self.rasterize_resolved(key, c, |rasterizer, face| {
+            let index = glyph_index(face, c)?;
+            let (gm, em) = platform_call("IDWriteFontFace::GetDesignGlyphMetrics", AssertUnwindSafe(|| {
+                (face.get_design_glyph_metrics(&[index], false)[0], face.metrics().designUnitsPerEm)
+            }))?;
+            let ink_height = gm.advanceHeight as i32 - (gm.topSideBearing + gm.bottomSideBearing);
+            if ink_height <= 0 || target_height_px == 0 {
+                return Ok(empty_glyph(c));
             }
+
+            // Bitmap dimensions are truncated; aim for the middle of the target pixel so
+            // rounding error can't lose a row.
+            let size = (target_height_px as f32 + 0.5) * em as f32 / ink_height as f32;
+            rasterizer.rasterize_index(face, c, index, size)
+        })?;
+        bitmap::pad_rows(&mut glyph, self.options.row_alignment);
+        Ok(glyph)
+    }
+
+    /// Check that `size` produces renderable glyphs for the font at `key`
+    ///
+    /// Some size/DPI combinations scale glyphs down to nothing. This rasterizes a reference
+    /// glyph and fails with `Error::InvalidSize` when the result is empty.
+    pub fn validate_size(&mut self, key: FontKey, size: Size) -> Result<(), Error> {
+        let glyph = self.get_glyph(&GlyphKey { c: 'A', font_key: key, size: size })?;
+
+        if glyph.width <= 0 || glyph.height <= 0 || glyph.buf.iter().all(|&b| b == 0) {
+            return Err(Error::InvalidSize(size));
         }
+
+        Ok(())
+    }
+
+    /// Render only the outline of glyphs, `stroke` pixels wide, leaving the interior transparent
+    ///
+    /// dwrote has no way to stroke glyph outlines with an outline sink, so this works on the
+    /// rasterized coverage instead: covered pixels more than `stroke` pixels inside the glyph
+    /// are cleared (see `bitmap::hollow`). The stroke is measured in whole pixels and follows the
+    /// antialiased edge rather than the exact outline. Passing `None` restores normal filled
+    /// rendering.
+    pub fn set_hollow_stroke(&mut self, stroke: Option<u32>) {
+        self.options.hollow_stroke = stroke;
+        self.cache.clear();
+    }
+
+    /// Set how glyphs with side bearings larger than their advance are sized
+    pub fn set_bearing_overflow(&mut self, overflow: BearingOverflow) {
+        self.options.bearing_overflow = overflow;
+        self.cache.clear();
+    }
+
+    /// Snap the glyph origin to the pixel grid after scaling
+    ///
+    /// This reduces blur when fractional layouts would place the bitmap between pixels.
+    pub fn set_snap_origin(&mut self, snap: bool) {
+        self.options.snap_origin = snap;
+        self.cache.clear();
+    }
+
+    /// Sharpen glyphs with an unsharp mask of strength `amount` after rasterization
+    ///
+    /// Passing `None` disables sharpening.
+    pub fn set_sharpen(&mut self, amount: Option<f32>) {
+        self.options.sharpen = amount;
+        self.cache.clear();
+    }
+
+    /// Override the gamma DirectWrite renders glyphs with, in place of the primary monitor's
+    ///
+    /// The gamma must be above 0 and at most 256; DirectWrite's default is usually 1.8 or 2.2.
+    pub fn set_gamma(&mut self, gamma: f32) -> Result<(), Error> {
+        self.rendering_params = Some(GammaRenderingParams::create(gamma)?);
+        self.options.gamma = Some(gamma);
+        self.cache.clear();
+        Ok(())
+    }
+
+    /// Invert the coverage of rendered glyphs
+    ///
+    /// Some bitmap fonts are drawn light-on-dark, with the background as ink. Inverting them
+    /// makes the glyph shapes the covered pixels again. Other filters see the inverted
+    /// coverage, and padding is still filled as configured.
+    pub fn set_invert_coverage(&mut self, invert: bool) {
+        self.options.invert_coverage = invert;
+        self.cache.clear();
+    }
+
+    /// Snap coverage towards fully on or off to crispen small text
+    ///
+    /// This is a crude stand-in for hinting at small sizes. Passing `None` disables it.
+    pub fn set_coverage_threshold(&mut self, threshold: Option<CoverageThreshold>) {
+        self.options.coverage_threshold = threshold;
+        self.cache.clear();
+    }
+
+    /// Keep every stem at least a pixel's worth of coverage
+    ///
+    /// At very small sizes thin stems can render fainter than a pixel, or collapse to almost
+    /// nothing even with hinting. This boosts their coverage back up; see `bitmap::min_stem`.
+    /// Glyphs larger than `MIN_STEM_SIZE` are left alone.
+    pub fn set_min_stem(&mut self, enabled: bool) {
+        self.options.min_stem = enabled;
+        self.cache.clear();
+    }
+
+    /// Set when glyphs are hinted; hinted glyphs are rendered with GDI compatible metrics
+    pub fn set_hinting(&mut self, hinting: Hinting) {
+        self.options.hinting = hinting;
+        self.cache.clear();
+    }
+
+    /// Angle for synthesizing an italic of the font at `key`, in degrees
+    ///
+    /// This comes from the `post` table of the family's italic face with the same weight and
+    /// stretch. DirectWrite returns a simulated oblique of the upright face when there's no
+    /// real italic, and those declare no angle.
+    fn synthetic_italic_angle(&self, key: FontKey) -> f32 {
+        let font = &self.fonts[&key];
+        let family = match self.library.get_font_family_by_name(&font.family_name()) {
+            Some(family) => family,
+            None => return DEFAULT_ITALIC_ANGLE,
+        };
+
+        let italic = family.get_first_matching_font(font.weight(), font.stretch(), FontStyle::Italic);
+        slanted_angle(&italic.create_font_face())
+    }
+
+    /// Whether glyphs rendered at `size` pixels are hinted
+    fn is_hinted(&self, size: f32) -> bool {
+        match self.options.hinting {
+            Hinting::Off => false,
+            Hinting::Full => true,
+            Hinting::Below(threshold) => size < threshold,
+        }
+    }
+
+    /// Surround glyphs with a border `size` pixels wide filled with the RGB coverage `fill`
+    ///
+    /// A fill of `[0, 0, 0]` is transparent; a visible color makes atlas bleed easy to spot.
+    pub fn set_padding(&mut self, size: u32, fill: [u8; 3]) {
+        self.options.padding = (size, fill);
+        self.cache.clear();
+    }
+
+    /// Pad each row of returned glyph buffers to a multiple of `alignment` bytes
+    ///
+    /// Use `row_stride` to find the padded row length of a glyph. An alignment of 1 leaves
+    /// rows tightly packed.
+    pub fn set_row_alignment(&mut self, alignment: usize) {
+        self.options.row_alignment = cmp::max(alignment, 1);
+        self.cache.clear();
+    }
+
+    /// Length in bytes of each row in the buffer of `glyph`
+    pub fn row_stride(&self, glyph: &RasterizedGlyph) -> usize {
+        bitmap::row_stride(glyph.width, self.options.row_alignment)
+    }
+
+    /// Resolve the font `c` is drawn from in place of the font at `key`, as `get_glyph` would
+    ///
+    /// Fallback faces can use a different number of design units per em than the font they
+    /// stand in for, so metrics for `c` have to be read and scaled using this font's face.
+    fn metrics_font(&mut self, key: FontKey, c: char) -> Result<FontKey, Error> {
+        let resolved = self.resolve_font(key, c)?;
+        if self.lost.contains(&resolved) && !is_invisible(c) {
+            return Err(Error::FontUnavailable(self.fonts[&resolved].family_name()));
+        }
+
+        Ok(resolved)
+    }
+
+    /// Scale factor from font design units to pixels at `size`
+    fn pixels_per_design_unit(&self, face: &FontFace, size: Size) -> f32 {
+        self.dpr * size.as_f32_pts() / face.metrics().designUnitsPerEm as f32
+    }
+
+    /// Rasterize `c` from `face` at `size` pixels
+    fn rasterize_glyph(&self, face: &FontFace, c: char, size: f32) -> Result<RasterizedGlyph, Error> {
+        if is_invisible(c) {
+            return Ok(empty_glyph(c));
+        }
+
+        let c_index = glyph_index(face, c)?;
+        self.rasterize_index_transformed(face, c, c_index, size, 0., None)
+    }
+
+    /// Rasterize the glyph at `c_index` in `face`; `c` is only recorded in the result
+    fn rasterize_index(&self, face: &FontFace, c: char, c_index: u16, size: f32) -> Result<RasterizedGlyph, Error> {
+        self.rasterize_index_at(face, c, c_index, size, 0.)
+    }
+
+    /// Rasterize the glyph at `c_index` shifted right by the fraction of a pixel `x_offset`
+    fn rasterize_index_at(
+        &self,
+        face: &FontFace,
+        c: char,
+        c_index: u16,
+        size: f32,
+        x_offset: f32
+    ) -> Result<RasterizedGlyph, Error> {
+        self.rasterize_index_transformed(face, c, c_index, size, x_offset, None)
+    }
+
+    /// Rasterize the glyph at `c_index` with `transform` applied around the pen position
+    ///
+    /// The bitmap grows to fit the transformed glyph; see `transformed_bounds`. Failures of the
+    /// DirectWrite calls, which read the font's file, are reported as `Error::PlatformError`.
+    fn rasterize_index_transformed(
+        &self,
+        face: &FontFace,
+        c: char,
+        c_index: u16,
+        size: f32,
+        x_offset: f32,
+        transform: Option<&DWRITE_MATRIX>
+    ) -> Result<RasterizedGlyph, Error> {
+        let (gm, em, vertical_metrics) = platform_call("IDWriteFontFace::GetDesignGlyphMetrics", AssertUnwindSafe(|| {
+            (face.get_design_glyph_metrics(&[c_index], false)[0], face.metrics().designUnitsPerEm,
+             has_vertical_metrics(face))
+        }))?;
+        let gm = with_horizontal_advance(gm, em, vertical_metrics, c);
+
+        // `size` is the em size passed to `draw_glyph_run`, so bounds scale by the same amount
+        let scaled_design_units_to_pixels = size / em as f32;
+
+        let mut width = ink_width(&gm, self.options.bearing_overflow) as f32 * scaled_design_units_to_pixels;
+        let mut height = (gm.advanceHeight as i32 - (gm.topSideBearing + gm.bottomSideBearing)) as f32 * scaled_design_units_to_pixels;
+        let (mut x, mut y) = glyph_origin(&gm, scaled_design_units_to_pixels, self.options.snap_origin);
+
+        // Snapping moves the ink by up to half a pixel, so leave room for it to move right or
+        // down into
+        if self.options.snap_origin {
+            width += 1.;
+            height += 1.;
+        }
+
+        // Make room for the shifted ink. The pen stays put, so the ink lands `x_offset` to its
+        // right.
+        if x_offset > 0. {
+            width += 1.;
+        }
+
+        if let Some(transform) = transform {
+            let bounds = transformed_bounds(transform, width, height, x, y);
+            width = bounds.0;
+            height = bounds.1;
+            x = bounds.2;
+            y = bounds.3;
+        }
+
+        // A size this small scales the glyph away entirely; don't ask GDI for an empty bitmap
+        if width < 1. || height < 1. {
+            return Ok(empty_glyph(c));
+        }
+
+        //let em_size = 10.0f32; // pulled this value from dwrite, but I'm not sure if it's correct. It's kind of a magic number...
+        let measuring_mode = if self.is_hinted(size) {
+            DWRITE_MEASURING_MODE_GDI_CLASSIC
+        } else {
+            DWRITE_MEASURING_MODE_NATURAL
+        };
+        let pen_x = x + x_offset;
+        let mask = platform_call("IDWriteBitmapRenderTarget::DrawGlyphRun", AssertUnwindSafe(|| {
+            let rt = self.gdi_interop.create_bitmap_render_target(width as u32, height as u32);
+            rt.set_pixels_per_dip(self.dpr);
+            if let Some(m) = transform {
+                // The render target transforms around the top left of the bitmap, so shift
+                // things back to keep the pen where it was
+                rt.set_current_transform(&DWRITE_MATRIX {
+                    m11: m.m11, m12: m.m12,
+                    m21: m.m21, m22: m.m22,
+                    dx: pen_x + m.dx - (pen_x * m.m11 + y * m.m21),
+                    dy: y + m.dy - (pen_x * m.m12 + y * m.m22),
+                });
+            }
+
+            if let Some(ref params) = self.rendering_params {
+                draw_glyph_run_with(&rt, (pen_x, y), measuring_mode, face, size, c_index, params)?;
+            } else {
+                let rp = RenderingParams::create_for_primary_monitor();
+                rt.draw_glyph_run(pen_x, y,
+                                  measuring_mode,
+                                  &face,
+                                  size,
+                                  &[c_index],
+                                  &[0f32],
+                                  &[GlyphOffset { advanceOffset: 0., ascenderOffset: 0. }],
+                                  &rp,
+                                  &(255.0f32, 255.0f32, 255.0f32));
+            }
+
+            Ok(rt.get_opaque_values_as_mask())
+        })).and_then(|mask| mask)?;
+        let bytes = bitmap::crop(mask, width as usize, height as usize);
+
+        let mut glyph = RasterizedGlyph {
+            c: c,
+            top: y as i32,
+            left: x as i32,
+            width: width as i32,
+            height: height as i32,
+            buf: bytes,
+        };
+
+        self.post_process(&mut glyph, size);
+        Ok(glyph)
+    }
+
+    /// Apply the configured filters to a freshly rasterized glyph drawn `size` pixels high
+    fn post_process(&self, glyph: &mut RasterizedGlyph, size: f32) {
+        if self.options.invert_coverage {
+            bitmap::invert(glyph);
+        }
+
+        if self.options.min_stem && size <= MIN_STEM_SIZE {
+            bitmap::min_stem(glyph);
+        }
+
+        if let Some(CoverageThreshold { threshold, softness }) = self.options.coverage_threshold {
+            bitmap::threshold(glyph, threshold, softness);
+        }
+
+        if let Some(amount) = self.options.sharpen {
+            bitmap::sharpen(glyph, amount);
+        }
+
+        if let Some(stroke) = self.options.hollow_stroke {
+            bitmap::hollow(glyph, stroke);
+        }
+
+        let (size, fill) = self.options.padding;
+        bitmap::pad(glyph, size, fill);
+    }
+
+    /// Load a font accoring to `FontDesc`
+    fn get_font(&mut self, desc: &FontDesc) -> Result<Font, Error> {
+        match desc.style {
+            Style::Description { slant, weight } => {
+                // Match nearest font
+                self.get_matching_font(&desc, slant, weight)
+            }
+            Style::Specific(ref style) => {
+                // If a name was specified, try and load specifically that font.
+                self.get_specific_font(&desc, &style)
+            }
+        }
+    }
+
+    /// Find the family named by `desc`
+    ///
+    /// dwrote panics rather than returning errors when matching against a family with no usable
+    /// fonts, so those are rejected here with `Error::MissingFont`.
+    fn get_family(&self, desc: &FontDesc) -> Result<FontFamily, Error> {
+        let family = self.library
+            .get_font_family_by_name(&desc.name)
+            .ok_or_else(|| Error::MissingFont(desc.to_owned()))?;
+
+        if family.get_font_count() == 0 {
+            return Err(Error::MissingFont(desc.to_owned()));
+        }
+
+        Ok(family)
+    }
+
+    fn get_matching_font(
+        &mut self,
+        desc: &FontDesc,
+        slant: Slant,
+        weight: Weight
+    ) -> Result<Font, Error> {
+        let family = self.get_family(desc)?;
+        //map slant to FontStyle and weight to FontWeight
+        let font_style = match slant {
+            Slant::Normal   => FontStyle::Normal,
+            Slant::Italic   => FontStyle::Italic,
+            Slant::Oblique  => FontStyle::Oblique
+        };
+        let font_weight = match weight {
+            Weight::Normal  => FontWeight::Regular,
+            Weight::Bold    => FontWeight::Bold,
+        };
+        Ok(family.get_first_matching_font(font_weight, FontStretch::Normal, font_style))
+    }
+
+    fn get_specific_font(
+        &mut self,
+        desc: &FontDesc,
+        style: &str
+    ) -> Result<Font, Error> {
+        let family = self.get_family(desc)?;
+        // parse style into either Normal, Bold, or Italic
+        // I guess this is how specific face is supposed to work? idk for sure...
+        let font_info = match style {
+            "Normal"    => (FontWeight::Regular, FontStretch::Normal, FontStyle::Normal),
+            "Bold"      => (FontWeight::Bold, FontStretch::Normal, FontStyle::Normal),
+            "Italic"    => (FontWeight::Regular, FontStretch::Normal, FontStyle::Italic),
+            &_          => (FontWeight::Regular, FontStretch::Normal, FontStyle::Normal),
+        };
+        Ok(family.get_first_matching_font(font_info.0, font_info.1, font_info.2))
+    }
+}
+
+/// Errors occurring when using the directwrite rasterizer
+#[derive(Debug, Clone)]
+pub enum Error {
+    /// Couldn't find font matching description
+    MissingFont(FontDesc),
+
+    /// Requested an operation with a FontKey that isn't known to the rasterizer
+    FontNotLoaded,
+
+    /// Glyphs rasterized at this size have no visible output
+    InvalidSize(Size),
+
+    /// The font is missing a table needed for the operation, or it's malformed
+    MissingTable(&'static str),
+
+    /// A DirectWrite call failed; holds the name of the call
+    PlatformError(&'static str),
+
+    /// A loaded font can't be read any more and no fallback has the glyph; holds its family
+    FontUnavailable(String),
+}
+
+impl ::std::error::Error for Error {
+    fn cause(&self) -> Option<&::std::error::Error> {
+        None
+    }
+
+    fn description(&self) -> &str {
+        match *self {
+            Error::MissingFont(ref _desc) => "couldn't find the requested font",
+            Error::FontNotLoaded => "tried to operate on font that hasn't been loaded",
+            Error::InvalidSize(_) => "font size produces empty glyphs",
+            Error::MissingTable(_) => "font is missing a required table",
+            Error::PlatformError(_) => "a DirectWrite call failed",
+            Error::FontUnavailable(_) => "font is no longer available",
+        }
+    }
+}
+
+impl ::std::fmt::Display for Error {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        match *self {
+            Error::MissingFont(ref desc) => {
+                write!(f, "Couldn't find a font with {}\
+                       \n\tPlease check the font config in your alacritty.yml.", desc)
+            },
+            Error::FontNotLoaded => {
+                f.write_str("Tried to use a font that hasn't been loaded")
+            },
+            Error::InvalidSize(size) => {
+                write!(f, "Font size {}pt produces empty glyphs", size.as_f32_pts())
+            },
+            Error::MissingTable(table) => {
+                write!(f, "Font has no usable '{}' table", table)
+            },
+            Error::PlatformError(call) => {
+                write!(f, "DirectWrite call {} failed; is there a desktop session?", call)
+            },
+            Error::FontUnavailable(ref family) => {
+                write!(f, "Font {} is no longer available, and no other font has the glyph\
+                       \n\tWas it uninstalled?", family)
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ptr;
+
+    use gdi32;
+    use winapi;
+
+    use super::{BearingOverflow, ColorSpace, DwroteRasterizer, Error, GlyphMetrics, Hinting, TabMode};
+    use super::{CoverageThreshold, Transform};
+    use dwrote::{DWRITE_FONT_METRICS, FontCollection, FontStretch, FontStyle, FontWeight, GdiInterop};
+    use dwrote::{DWRITE_MEASURING_MODE_GDI_CLASSIC, DWRITE_MEASURING_MODE_NATURAL};
+    use ::{FontDesc, FontKey, GlyphKey, Rasterize, RasterizedGlyph, Size, Slant, Style, Weight};
+    use ::is_blank_glyph;
+
+    fn rasterizer_with_font(name: &str) -> (DwroteRasterizer, FontKey) {
+        rasterizer_with_style(name, Weight::Normal)
+    }
+
+    fn rasterizer_with_style(name: &str, weight: Weight) -> (DwroteRasterizer, FontKey) {
+        let mut rasterizer = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        let desc = FontDesc::new(name, Style::Description {
+            slant: Slant::Normal,
+            weight: weight,
+        });
+        let key = rasterizer.load_font(&desc, Size::new(12.)).unwrap();
+        (rasterizer, key)
+    }
+
+    #[test]
+    fn validate_size() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+
+        assert!(rasterizer.validate_size(key, Size::new(12.)).is_ok());
+        assert!(rasterizer.validate_size(key, Size::new(0.5)).is_err());
+    }
+
+    #[test]
+    fn metrics_with_mode() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let gdi = rasterizer.metrics_with_mode(key, size, DWRITE_MEASURING_MODE_GDI_CLASSIC).unwrap();
+        assert_eq!(gdi.average_advance.fract(), 0.);
+
+        let natural = rasterizer.metrics_with_mode(key, size, DWRITE_MEASURING_MODE_NATURAL).unwrap();
+        assert!(natural.average_advance.fract() != 0.);
+    }
+
+    /// Advance of `c` in pixels as GDI itself measures it, for `family` with an `em` pixel height
+    fn gdi_advance(family: &str, em: i32, c: char) -> i32 {
+        let name: Vec<u16> = family.encode_utf16().chain(Some(0)).collect();
+        let mut width = 0;
+
+        unsafe {
+            let dc = gdi32::CreateCompatibleDC(ptr::null_mut());
+            let font = gdi32::CreateFontW(-em, 0, 0, 0, winapi::FW_NORMAL as _, 0, 0, 0,
+                                          winapi::DEFAULT_CHARSET as _, winapi::OUT_DEFAULT_PRECIS as _,
+                                          winapi::CLIP_DEFAULT_PRECIS as _, winapi::DEFAULT_QUALITY as _,
+                                          winapi::DEFAULT_PITCH as _, name.as_ptr());
+            let old = gdi32::SelectObject(dc, font as winapi::HGDIOBJ);
+            gdi32::GetCharWidth32W(dc, c as u32, c as u32, &mut width);
+            gdi32::SelectObject(dc, old);
+            gdi32::DeleteObject(font as winapi::HGDIOBJ);
+            gdi32::DeleteDC(dc);
+        }
+
+        width
+    }
+
+    #[test]
+    fn gdi_classic_metrics_match_gdi() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+
+        for em in 8..33 {
+            let size = Size::new(em as f32);
+            let gdi = rasterizer.metrics_with_mode(key, size, DWRITE_MEASURING_MODE_GDI_CLASSIC).unwrap();
+            assert_eq!(gdi.average_advance, gdi_advance("Consolas", em, 'A') as f64, "em {}", em);
+        }
+    }
+
+    #[test]
+    fn raw_glyph_metrics() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let raw = rasterizer.raw_glyph_metrics(key, 'A').unwrap();
+        let em = rasterizer.faces[&key].metrics().designUnitsPerEm as f64;
+        let scaled = raw.advanceWidth as f64 * size.as_f32_pts() as f64 / em;
+
+        let metrics = rasterizer.metrics(key, size).unwrap();
+        assert!((scaled - metrics.average_advance).abs() < 1e-6);
+    }
+
+    #[test]
+    fn ink_width_with_overflowing_bearings() {
+        let gm = GlyphMetrics {
+            leftSideBearing: 700,
+            advanceWidth: 1000,
+            rightSideBearing: 500,
+            topSideBearing: 0,
+            advanceHeight: 2048,
+            bottomSideBearing: 0,
+            verticalOriginY: 1600,
+        };
+
+        assert_eq!(super::ink_width(&gm, BearingOverflow::Zero), 0);
+        assert_eq!(super::ink_width(&gm, BearingOverflow::Advance), 1000);
+    }
+
+    #[test]
+    fn glyph_origin_snapping() {
+        let gm = GlyphMetrics {
+            leftSideBearing: -37,
+            advanceWidth: 1126,
+            rightSideBearing: 80,
+            topSideBearing: 301,
+            advanceHeight: 2048,
+            bottomSideBearing: 400,
+            verticalOriginY: 1901,
+        };
+        let scale = 12. / 2048.;
+
+        let (x, y) = super::glyph_origin(&gm, scale, false);
+        assert!(x.fract() != 0. && y.fract() != 0.);
+
+        let (x, y) = super::glyph_origin(&gm, scale, true);
+        assert_eq!(x.fract(), 0.);
+        assert_eq!(y.fract(), 0.);
+    }
+
+    #[test]
+    fn snapped_glyphs_keep_their_ink() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let coverage = |glyph: &RasterizedGlyph| glyph.buf.iter().map(|&b| b as u32).sum::<u32>();
+
+        for c in (b'A'..b'[').map(|c| c as char) {
+            let glyph_key = GlyphKey { c: c, font_key: key, size: Size::new(13.) };
+            rasterizer.set_snap_origin(false);
+            let free = rasterizer.get_glyph(&glyph_key).unwrap();
+            rasterizer.set_snap_origin(true);
+            let snapped = rasterizer.get_glyph(&glyph_key).unwrap();
+
+            assert!(snapped.width > free.width);
+            assert!(coverage(&snapped) * 20 >= coverage(&free) * 19, "{:?} lost ink when snapped", c);
+        }
+    }
+
+    #[test]
+    fn resolved_style_numeric() {
+        let (rasterizer, key) = rasterizer_with_style("Consolas", Weight::Bold);
+
+        let style = rasterizer.resolved_style_numeric(key).unwrap();
+        assert_eq!(style.weight, 700);
+        assert_eq!(style.slant, 0.);
+
+        // MS Gothic has no italic, so this is a simulated oblique, which declares no angle
+        let mut rasterizer = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        let desc = FontDesc::new("MS Gothic", Style::Description {
+            slant: Slant::Italic,
+            weight: Weight::Normal,
+        });
+        let key = rasterizer.load_font(&desc, Size::new(12.)).unwrap();
+        assert_eq!(rasterizer.resolved_style_numeric(key).unwrap().slant, super::DEFAULT_ITALIC_ANGLE);
+    }
+
+    #[test]
+    fn noncharacters_are_empty() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+
+        for &c in &['\u{FFFE}', '\u{FFFF}', '\u{FDD0}', '\u{10FFFF}'] {
+            let glyph = rasterizer.get_glyph(&GlyphKey { c: c, font_key: key, size: Size::new(12.) }).unwrap();
+            assert_eq!(glyph.width, 0);
+            assert!(glyph.buf.is_empty());
+        }
+
+        assert!(!super::is_noncharacter('A'));
+        assert!(!super::is_noncharacter('\u{FFFD}'));
+    }
+
+    #[test]
+    fn get_glyph_weighted() {
+        let (mut rasterizer, key) = rasterizer_with_font("Segoe UI");
+        let size = Size::new(24.);
+        let coverage = |glyph: RasterizedGlyph| {
+            glyph.buf.iter().map(|&b| b as u32).sum::<u32>()
+        };
+
+        let regular = coverage(rasterizer.get_glyph_weighted(key, 'H', size, 400.).unwrap());
+        let medium = coverage(rasterizer.get_glyph_weighted(key, 'H', size, 550.).unwrap());
+        let bold = coverage(rasterizer.get_glyph_weighted(key, 'H', size, 700.).unwrap());
+
+        assert!(regular < medium && medium < bold);
+    }
+
+    #[test]
+    fn export_metrics() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+        let chars = ['A', 'g', '"', '\\'];
+
+        let json = rasterizer.export_metrics(key, &chars, Size::new(12.)).unwrap();
+        let value: ::serde_json::Value = ::serde_json::from_str(&json).unwrap();
+
+        let entries = value.as_array().unwrap();
+        assert_eq!(entries.len(), chars.len());
+        for (entry, &c) in entries.iter().zip(chars.iter()) {
+            assert_eq!(entry.find("char").and_then(|c| c.as_str()), Some(&c.to_string()[..]));
+        }
+    }
+
+    #[test]
+    fn missing_family_is_an_error() {
+        let mut rasterizer = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        let desc = FontDesc::new("Alacritty Missing Font Family", Style::Description {
+            slant: Slant::Normal,
+            weight: Weight::Normal,
+        });
+
+        match rasterizer.load_font(&desc, Size::new(12.)) {
+            Err(Error::MissingFont(missing)) => assert_eq!(missing, desc),
+            other => panic!("expected MissingFont, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn get_glyph_cached() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'a', font_key: key, size: Size::new(12.) };
+
+        let (first, from_cache) = rasterizer.get_glyph_cached(&glyph_key).unwrap();
+        assert!(!from_cache);
+
+        let (second, from_cache) = rasterizer.get_glyph_cached(&glyph_key).unwrap();
+        assert!(from_cache);
+        assert_eq!(first.buf, second.buf);
+
+        rasterizer.set_snap_origin(true);
+        let (_, from_cache) = rasterizer.get_glyph_cached(&glyph_key).unwrap();
+        assert!(!from_cache);
+    }
+
+    #[test]
+    fn iterate_glyphs() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+        let count = rasterizer.faces[&key].get_glyph_count() as usize;
+
+        let glyphs = rasterizer.iterate_glyphs(key, Size::new(12.)).unwrap();
+        assert_eq!(glyphs.size_hint(), (count, Some(count)));
+        assert_eq!(glyphs.count(), count);
+    }
+
+    #[test]
+    fn synthesized_underline() {
+        let mut dm = DWRITE_FONT_METRICS {
+            designUnitsPerEm: 2048,
+            ascent: 1901,
+            descent: 483,
+            lineGap: 0,
+            capHeight: 1462,
+            xHeight: 1062,
+            underlinePosition: 0,
+            underlineThickness: 0,
+            strikethroughPosition: 530,
+            strikethroughThickness: 102,
+        };
+
+        let (position, thickness) = super::underline_design_units(&dm);
+        assert!(position < 0);
+        assert!(thickness > 0);
+
+        // Fonts that do provide underline metrics keep them
+        dm.underlinePosition = -217;
+        dm.underlineThickness = 150;
+        assert_eq!(super::underline_design_units(&dm), (-217, 150));
+    }
+
+    #[test]
+    fn place_mark_above() {
+        let base = RasterizedGlyph { c: 'a', width: 6, height: 7, top: 7, left: 1, buf: vec![255; 126] };
+        let mut acute = RasterizedGlyph { c: '\u{301}', width: 2, height: 3, top: 10, left: -4, buf: vec![255; 18] };
+
+        super::place_mark(&base, &mut acute, 7);
+
+        // Bottom of the mark clears the top of the base
+        assert!(acute.top - acute.height > base.top);
+        assert_eq!(acute.left, -1);
+
+        let mut cedilla = RasterizedGlyph { c: '\u{327}', width: 2, height: 2, top: 0, left: -4, buf: vec![255; 12] };
+        super::place_mark(&base, &mut cedilla, 7);
+        assert!(cedilla.top < 0);
+    }
+
+    #[test]
+    fn get_glyph_run_with_fallback() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let chars = ['a', '\u{65E5}', 'b'];
+
+        let glyphs = rasterizer.get_glyph_run(key, &chars, Size::new(12.)).unwrap();
+        assert_eq!(glyphs.len(), chars.len());
+        for (&(c, ref glyph), &expected) in glyphs.iter().zip(chars.iter()) {
+            assert_eq!(c, expected);
+            assert_eq!(glyph.c, expected);
+            assert!(glyph.width > 0);
+        }
+
+        assert_eq!(rasterizer.fallback_font(key, 'a'), None);
+        assert!(rasterizer.fallback_font(key, '\u{65E5}').is_some());
+    }
+
+    #[test]
+    fn ink_left_bearing() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let raw = rasterizer.raw_glyph_metrics(key, 'H').unwrap();
+        assert!(raw.leftSideBearing > 0);
+
+        let em = rasterizer.faces[&key].metrics().designUnitsPerEm as f32;
+        let expected = raw.leftSideBearing as f32 * size.as_f32_pts() / em;
+        let bearing = rasterizer.ink_left_bearing(key, 'H', size).unwrap();
+        assert!(bearing > 0.);
+        assert!((bearing - expected).abs() < 1e-4);
+    }
+
+    #[test]
+    fn glyph_buffer_matches_dimensions() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+
+        // Check enough glyphs that some have odd widths
+        let mut odd = false;
+        for c in "ilmwAW@|".chars() {
+            let glyph = rasterizer.get_glyph(&GlyphKey { c: c, font_key: key, size: Size::new(11.) }).unwrap();
+            assert_eq!(glyph.buf.len(), (glyph.width * glyph.height * 3) as usize);
+            odd |= glyph.width % 2 == 1;
+        }
+        assert!(odd);
+    }
+
+    #[test]
+    fn fallback_collection() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let emoji = '\u{1F600}';
+
+        // dwrote can't build collections from font files, so the system collection stands in
+        // for a bundled one; it has to be searched before the rasterizer's own copy.
+        rasterizer.set_fallback_collection(FontCollection::system());
+        {
+            let sources = rasterizer.fallback_sources();
+            assert_eq!(sources.len(), 2);
+            assert_eq!(sources[0] as *const FontCollection,
+                       rasterizer.fallback_collection.as_ref().unwrap() as *const FontCollection);
+            assert_eq!(sources[1] as *const FontCollection, &*rasterizer.library as *const FontCollection);
+        }
+
+        let glyph = rasterizer.get_glyph(&GlyphKey { c: emoji, font_key: key, size: Size::new(12.) }).unwrap();
+        assert!(glyph.buf.iter().any(|&b| b != 0));
+
+        // The fallback is the first font in the supplied collection with the emoji
+        let fallback = rasterizer.fallback_font(key, emoji).unwrap();
+        let expected = super::find_font_with_char(rasterizer.fallback_collection.as_ref().unwrap(), emoji,
+                                                  FontWeight::Regular, FontStretch::Normal, FontStyle::Normal, &[])
+            .unwrap();
+        assert_eq!(rasterizer.fonts[&fallback].family_name(), expected.family_name());
+    }
+
+    #[test]
+    fn glyph_overflows_cell() {
+        let (mut rasterizer, key) = rasterizer_with_font("MS Gothic");
+        let size = Size::new(12.);
+
+        assert!(rasterizer.glyph_overflows_cell(key, '\u{65E5}', size).unwrap());
+        assert!(!rasterizer.glyph_overflows_cell(key, 'A', size).unwrap());
+    }
+
+    #[test]
+    fn zwj_sequence_falls_back_to_components() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let family = "\u{1F468}\u{200D}\u{1F469}\u{200D}\u{1F467}";
+
+        let glyphs = rasterizer.get_zwj_sequence(key, family, Size::new(12.)).unwrap();
+        let chars = glyphs.iter().map(|glyph| glyph.c).collect::<Vec<_>>();
+        assert_eq!(chars, vec!['\u{1F468}', '\u{1F469}', '\u{1F467}']);
+        assert!(glyphs.iter().all(|glyph| glyph.buf.iter().any(|&b| b != 0)));
+    }
+
+    #[test]
+    fn get_glyph_in_color_space() {
+        let (mut rasterizer, key) = rasterizer_with_font("Segoe UI Emoji");
+        let glyph_key = GlyphKey { c: '\u{1F600}', font_key: key, size: Size::new(24.) };
+
+        let srgb = rasterizer.get_glyph_in_color_space(&glyph_key, ColorSpace::Srgb).unwrap();
+        let linear = rasterizer.get_glyph_in_color_space(&glyph_key, ColorSpace::Linear).unwrap();
+        assert_eq!(srgb.color_space, ColorSpace::Srgb);
+        assert_eq!(linear.color_space, ColorSpace::Linear);
+
+        let midtones = srgb.glyph.buf.iter()
+            .zip(linear.glyph.buf.iter())
+            .filter(|&(&s, _)| s > 32 && s < 224)
+            .collect::<Vec<_>>();
+        assert!(!midtones.is_empty());
+        for (s, l) in midtones {
+            assert!(l < s);
+        }
+    }
+
+    #[test]
+    fn hinting_below_threshold() {
+        let mut rasterizer = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        assert!(!rasterizer.is_hinted(10.));
+
+        rasterizer.set_hinting(Hinting::Below(16.));
+        assert!(rasterizer.is_hinted(10.));
+        assert!(!rasterizer.is_hinted(30.));
+    }
+
+    #[test]
+    fn contour_count() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+
+        assert_eq!(rasterizer.contour_count(key, 'o').unwrap(), 2);
+        assert_eq!(rasterizer.contour_count(key, 'l').unwrap(), 1);
+        assert_eq!(rasterizer.contour_count(key, ' ').unwrap(), 0);
+    }
+
+    #[test]
+    fn metrics_scale() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let base = rasterizer.metrics(key, size).unwrap();
+        rasterizer.set_metrics_scale(1.25);
+        let zoomed = rasterizer.metrics(key, size).unwrap();
+
+        assert!((zoomed.average_advance - base.average_advance * 1.25).abs() < 1e-6);
+        assert!((zoomed.line_height - base.line_height * 1.25).abs() < 1e-6);
+    }
+
+    #[test]
+    fn metrics_scale_keeps_cell_counts() {
+        let (mut rasterizer, key) = rasterizer_with_font("MS Gothic");
+        let size = Size::new(12.);
+        let kanji = '\u{65E5}';
+
+        rasterizer.set_metrics_scale(2.);
+        let cell = rasterizer.metrics(key, size).unwrap().average_advance as f32;
+        assert_eq!(rasterizer.grid_advance(key, kanji, size).unwrap(), 2. * cell);
+        assert_eq!(rasterizer.grid_advance(key, 'A', size).unwrap(), cell);
+
+        rasterizer.set_metrics_scale(0.5);
+        assert!(rasterizer.glyph_overflows_cell(key, kanji, size).unwrap());
+        assert!(!rasterizer.glyph_overflows_cell(key, 'A', size).unwrap());
+    }
+
+    #[test]
+    fn freeze_fallback() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let kanji = '\u{65E5}';
+
+        rasterizer.get_glyph(&GlyphKey { c: kanji, font_key: key, size: size }).unwrap();
+        let pinned = rasterizer.fallback_font(key, kanji).unwrap();
+        rasterizer.freeze_fallback();
+
+        rasterizer.set_fallback_collection(FontCollection::system());
+        for _ in 0..3 {
+            rasterizer.get_glyph(&GlyphKey { c: kanji, font_key: key, size: size }).unwrap();
+            assert_eq!(rasterizer.fallback_font(key, kanji), Some(pinned));
+        }
+
+        // Characters that weren't resolved before freezing don't fall back
+        rasterizer.get_glyph(&GlyphKey { c: '\u{6708}', font_key: key, size: size }).unwrap();
+        assert_eq!(rasterizer.fallback_font(key, '\u{6708}'), None);
+    }
+
+    #[test]
+    fn vertical_only_metrics() {
+        let gm = GlyphMetrics {
+            leftSideBearing: 0,
+            advanceWidth: 0,
+            rightSideBearing: 0,
+            topSideBearing: 100,
+            advanceHeight: 1024,
+            bottomSideBearing: 60,
+            verticalOriginY: 900,
+        };
+
+        let fixed = super::with_horizontal_advance(gm, 1024, true, '\u{65E5}');
+        assert_eq!(fixed.advanceWidth, 1024);
+        assert_eq!(super::ink_width(&fixed, BearingOverflow::Zero), 1024);
+
+        // Without vertical metrics of its own, the vertical advance is made up by DirectWrite
+        let horizontal = super::with_horizontal_advance(gm, 1024, false, '\u{65E5}');
+        assert_eq!(horizontal.advanceWidth, 0);
+
+        // Marks, variation selectors and format characters are supposed to have no advance
+        for &c in &['\u{301}', '\u{5B4}', '\u{64E}', '\u{941}', '\u{E34}', '\u{FE0F}', '\u{200D}'] {
+            assert_eq!(super::with_horizontal_advance(gm, 1024, true, c).advanceWidth, 0);
+        }
+    }
+
+    #[test]
+    fn zero_advance_marks_keep_no_advance() {
+        // Arial has zero-advance Hebrew points and no vertical metrics
+        let (mut rasterizer, key) = rasterizer_with_font("Arial");
+        assert!(!super::has_vertical_metrics(&rasterizer.faces[&key]));
+        assert_eq!(rasterizer.glyph_advance(key, '\u{5B4}', Size::new(12.)).unwrap(), 0.);
+    }
+
+    #[test]
+    fn get_glyph_fit_height() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+
+        for &target in &[7, 16, 33] {
+            let glyph = rasterizer.get_glyph_fit_height(key, 'H', target).unwrap();
+            assert_eq!(glyph.height, target as i32);
+        }
+    }
+
+    #[test]
+    fn subpixel_phases() {
+        for phases in 1..5 {
+            let mut seen = (0..100)
+                .map(|i| super::subpixel_phase(i as f32 / 100. + 7., phases))
+                .collect::<Vec<_>>();
+            seen.sort();
+            seen.dedup();
+
+            assert_eq!(seen, (0..phases).collect::<Vec<_>>());
+        }
+
+        assert_eq!(super::subpixel_phase(-0.25, 4), 3);
+    }
+
+    #[test]
+    fn subpixel_ink_moves_right() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        rasterizer.set_subpixel_phases(4);
+        let glyph_key = GlyphKey { c: 'l', font_key: key, size: Size::new(12.) };
+
+        // Center of the ink, relative to the pen
+        let centers = (0..4)
+            .map(|phase| {
+                let glyph = rasterizer.get_glyph_subpixel(&glyph_key, phase as f32 / 4.).unwrap();
+                let (mut sum, mut total) = (0., 0.);
+                for (i, &b) in glyph.buf.iter().enumerate() {
+                    let column = (i / 3) % glyph.width as usize;
+                    sum += column as f32 * b as f32;
+                    total += b as f32;
+                }
+                sum / total - glyph.left as f32
+            })
+            .collect::<Vec<_>>();
+
+        assert!(centers.windows(2).all(|pair| pair[1] > pair[0]));
+        assert!(centers[3] - centers[0] < 1.);
+    }
+
+    #[test]
+    fn render_equal() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        assert!(rasterizer.render_equal(key, ' ', ' ', size).unwrap());
+        assert!(rasterizer.render_equal(key, 'a', 'a', size).unwrap());
+        assert!(!rasterizer.render_equal(key, 'a', 'b', size).unwrap());
+    }
+
+    #[test]
+    fn bom_is_invisible() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let glyph = rasterizer.get_glyph(&GlyphKey { c: '\u{FEFF}', font_key: key, size: size }).unwrap();
+        assert_eq!((glyph.width, glyph.height), (0, 0));
+        assert!(glyph.buf.is_empty());
+
+        assert_eq!(rasterizer.glyph_advance(key, '\u{FEFF}', size).unwrap(), 0.);
+        assert!(rasterizer.glyph_advance(key, 'A', size).unwrap() > 0.);
+    }
+
+    #[test]
+    fn grid_dimensions() {
+        let (rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let viewport = (800, 600);
+
+        let metrics = rasterizer.metrics(key, size).unwrap();
+        let (columns, rows) = rasterizer.grid_dimensions(key, size, viewport).unwrap();
+
+        assert_eq!(columns, (viewport.0 as f64 / metrics.average_advance) as u32);
+        assert_eq!(rows, (viewport.1 as f64 / metrics.line_height) as u32);
+    }
+
+    #[test]
+    fn negative_advance_is_clamped() {
+        let gm = GlyphMetrics {
+            leftSideBearing: 0,
+            advanceWidth: -200i32 as u32,
+            rightSideBearing: 0,
+            topSideBearing: 0,
+            advanceHeight: 2048,
+            bottomSideBearing: 0,
+            verticalOriginY: 1600,
+        };
+
+        assert_eq!(super::clamp_advance(gm, 'A').advanceWidth, 0);
+        assert_eq!(super::with_horizontal_advance(gm, 2048, true, 'A').advanceWidth, 0);
+
+        let positive = GlyphMetrics { advanceWidth: 1200, ..gm };
+        assert_eq!(super::clamp_advance(positive, 'B').advanceWidth, 1200);
+
+        let warned = super::NEGATIVE_ADVANCES.with(|warned| warned.borrow().clone());
+        assert_eq!(warned.into_iter().collect::<Vec<_>>(), vec!['A']);
+    }
+
+    #[test]
+    fn italic_shear() {
+        assert_eq!(super::italic_shear(0.), 0.);
+        assert!((super::italic_shear(-45.) - 1.).abs() < 1e-6);
+        assert!(super::italic_shear(super::DEFAULT_ITALIC_ANGLE) > 0.);
+    }
+
+    #[test]
+    fn synthetic_italic_uses_declared_angle() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let italic = FontDesc::new("Consolas", Style::Description {
+            slant: Slant::Italic,
+            weight: Weight::Normal,
+        });
+        let italic_key = rasterizer.load_font(&italic, Size::new(12.)).unwrap();
+
+        let declared = super::italic_angle(&rasterizer.faces[&italic_key]).unwrap();
+        assert!(declared != super::DEFAULT_ITALIC_ANGLE);
+        assert_eq!(rasterizer.synthetic_italic_angle(key), declared);
+
+        let glyph_key = GlyphKey { c: 'l', font_key: key, size: Size::new(12.) };
+        let upright = rasterizer.get_glyph(&glyph_key).unwrap();
+        let sheared = rasterizer.get_glyph_synthetic_italic(&glyph_key).unwrap();
+        assert!(sheared.height >= upright.height);
+        assert!(sheared.width > upright.width);
+    }
+
+    #[test]
+    fn failed_platform_call_is_an_error() {
+        let result = super::platform_call("GdiInterop::create", || -> GdiInterop {
+            panic!("simulated GdiInterop::create failure")
+        });
+
+        match result {
+            Err(Error::PlatformError(call)) => assert_eq!(call, "GdiInterop::create"),
+            other => panic!("expected PlatformError, got {:?}", other.map(|_| ())),
+        }
+
+        assert_eq!(super::platform_call("noop", || 7).unwrap(), 7);
+    }
+
+    #[test]
+    fn invert_coverage() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'W', font_key: key, size: Size::new(12.) };
+
+        let normal = rasterizer.get_glyph(&glyph_key).unwrap();
+        rasterizer.set_invert_coverage(true);
+        let inverted = rasterizer.get_glyph(&glyph_key).unwrap();
+
+        assert_eq!(normal.buf.len(), inverted.buf.len());
+        let high = normal.buf.iter().position(|&b| b > 200).unwrap();
+        let low = normal.buf.iter().position(|&b| b < 50).unwrap();
+        assert!(inverted.buf[high] < 55);
+        assert!(inverted.buf[low] > 205);
+    }
+
+    #[test]
+    fn gamma_changes_midtones() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'W', font_key: key, size: Size::new(12.) };
+
+        rasterizer.set_gamma(1.).unwrap();
+        let linear = rasterizer.get_glyph(&glyph_key).unwrap();
+        rasterizer.set_gamma(2.2).unwrap();
+        let corrected = rasterizer.get_glyph(&glyph_key).unwrap();
+
+        assert_eq!(linear.buf.len(), corrected.buf.len());
+        let pixels = linear.buf.iter().cloned().zip(corrected.buf.iter().cloned()).collect::<Vec<_>>();
+        let midtones = pixels.iter().filter(|&&(before, _)| before > 32 && before < 224).collect::<Vec<_>>();
+        assert!(!midtones.is_empty());
+        assert!(midtones.iter().any(|&&(before, after)| before != after));
+        assert!(pixels.iter().all(|&(before, after)| before != 0 || after == 0));
+    }
+
+    #[test]
+    fn coverage_threshold() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'W', font_key: key, size: Size::new(12.) };
+        let opaque = |glyph: &RasterizedGlyph| glyph.buf.iter().filter(|&&b| b == 255).count();
+
+        let normal = rasterizer.get_glyph(&glyph_key).unwrap();
+        rasterizer.set_coverage_threshold(Some(CoverageThreshold { threshold: 64, softness: 96 }));
+        let thresholded = rasterizer.get_glyph(&glyph_key).unwrap();
+
+        assert_eq!(normal.buf.len(), thresholded.buf.len());
+        assert!(opaque(&thresholded) > opaque(&normal));
+        for (&before, &after) in normal.buf.iter().zip(thresholded.buf.iter()) {
+            if before == 0 {
+                assert_eq!(after, 0);
+            }
+        }
+    }
+
+    #[test]
+    fn system_collection_is_shared() {
+        let a = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        let b = DwroteRasterizer::with_collection(a.collection(), 96., 96., 2.).unwrap();
+
+        assert_eq!(&*a.library as *const FontCollection, &*b.library as *const FontCollection);
+    }
+
+    #[test]
+    fn grid_advance() {
+        assert_eq!(super::cells_for_advance(0., 7.), 0);
+        assert_eq!(super::cells_for_advance(2., 7.), 1);
+        assert_eq!(super::cells_for_advance(7.5, 7.), 1);
+        assert_eq!(super::cells_for_advance(11., 7.), 2);
+        assert_eq!(super::cells_for_advance(30., 7.), 2);
+
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let cell = rasterizer.metrics(key, size).unwrap().average_advance as f32;
+        assert_eq!(rasterizer.grid_advance(key, 'A', size).unwrap(), cell);
+    }
+
+    #[test]
+    fn fallback_metrics_use_their_own_em() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let c = '\u{65E5}';
+
+        let glyph = rasterizer.get_glyph(&GlyphKey { c: c, font_key: key, size: size }).unwrap();
+        let fallback = rasterizer.fallback_font(key, c).unwrap();
+
+        // Both faces should scale their own design units to the same requested size
+        let em = rasterizer.faces[&fallback].metrics().designUnitsPerEm as f32;
+        let gm = rasterizer.raw_glyph_metrics(fallback, c).unwrap();
+        let expected = gm.advanceWidth as f32 * size.as_f32_pts() / em;
+        assert!((rasterizer.glyph_advance(key, c, size).unwrap() - expected).abs() < 1e-4);
+        assert!(glyph.height as f32 <= size.as_f32_pts() * 1.5);
+
+        let a = rasterizer.glyph_advance(key, 'A', size).unwrap() as f64;
+        assert!((a - rasterizer.metrics(key, size).unwrap().average_advance).abs() < 1e-4);
+    }
+
+    #[test]
+    fn rasterize_batch_parallel() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let keys = (b'!'..b'~')
+            .map(|c| GlyphKey { c: c as char, font_key: key, size: Size::new(12.) })
+            .collect::<Vec<_>>();
+
+        let parallel = rasterizer.rasterize_batch_parallel(&keys);
+        assert_eq!(parallel.len(), keys.len());
+
+        for (glyph_key, glyph) in keys.iter().zip(parallel.into_iter()) {
+            let glyph = glyph.unwrap();
+            let expected = rasterizer.get_glyph(glyph_key).unwrap();
+            assert_eq!(glyph.c, expected.c);
+            assert_eq!((glyph.left, glyph.top), (expected.left, expected.top));
+            assert_eq!((glyph.width, glyph.height), (expected.width, expected.height));
+            assert_eq!(glyph.buf, expected.buf);
+        }
+
+        assert!(rasterizer.rasterize_batch_parallel(&[]).is_empty());
+    }
+
+    #[test]
+    fn elastic_tab_advance() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let cell = rasterizer.metrics(key, size).unwrap().average_advance as f32;
+
+        assert_eq!(rasterizer.tab_advance(key, size, 3).unwrap(), 8. * cell);
+
+        rasterizer.set_tab_mode(TabMode::Elastic);
+        for &col in &[0u32, 3, 7, 8, 13] {
+            let advance = rasterizer.tab_advance(key, size, col).unwrap();
+            let end = col + (advance / cell).round() as u32;
+            assert_eq!(end % 8, 0);
+            assert!(end > col && end <= col + 8);
+        }
+    }
+
+    #[test]
+    fn blank_glyphs() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+
+        let space = rasterizer.get_glyph(&GlyphKey { c: ' ', font_key: key, size: size }).unwrap();
+        let letter = rasterizer.get_glyph(&GlyphKey { c: 'A', font_key: key, size: size }).unwrap();
+
+        assert!(is_blank_glyph(&space));
+        assert!(!is_blank_glyph(&letter));
+    }
+
+    #[test]
+    fn locale_picks_regional_han_forms() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: '\u{9AA8}', font_key: key, size: Size::new(24.) };
+
+        rasterizer.set_locale(Some("ja-JP"));
+        let japanese = rasterizer.get_glyph(&glyph_key).unwrap();
+        let japanese_font = rasterizer.fallback_font(key, glyph_key.c).unwrap();
+        let japanese_family = rasterizer.fonts[&japanese_font].family_name();
+
+        rasterizer.set_locale(Some("zh-CN"));
+        let chinese = rasterizer.get_glyph(&glyph_key).unwrap();
+        let chinese_font = rasterizer.fallback_font(key, glyph_key.c).unwrap();
+        let chinese_family = rasterizer.fonts[&chinese_font].family_name();
+
+        assert!(super::locale_families("ja-JP").contains(&&*japanese_family));
+        assert!(super::locale_families("zh-CN").contains(&&*chinese_family));
+        assert!(japanese.buf != chinese.buf);
+    }
+
+    #[test]
+    fn baseline_spacing() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let natural = rasterizer.metrics(key, size).unwrap();
+
+        rasterizer.set_baseline_spacing(40.);
+        let spaced = rasterizer.metrics(key, size).unwrap();
+        assert_eq!(spaced.line_height, 40.);
+        assert_eq!(spaced.average_advance, natural.average_advance);
+
+        rasterizer.set_baseline_spacing(0.);
+        assert_eq!(rasterizer.metrics(key, size).unwrap().line_height, natural.line_height);
+    }
+
+    #[test]
+    fn transformed_bounds() {
+        let identity = super::shear_matrix(0.);
+        assert_eq!(super::transformed_bounds(&identity, 10., 20., 0., 15.), (12., 22., 1., 16.));
+
+        // Leaning by half a pixel per pixel widens the box by half its height, plus rounding
+        let shear = super::shear_matrix(0.5);
+        let (width, height, x, y) = super::transformed_bounds(&shear, 10., 20., 0., 15.);
+        assert_eq!((width, height), (23., 22.));
+        // Descenders lean left of the pen, so it moves right to keep them in view
+        assert_eq!((x, y), (4., 16.));
+    }
+
+    #[test]
+    fn sheared_glyph_is_not_clipped() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'H', font_key: key, size: Size::new(24.) };
+        let glyph = rasterizer.get_glyph_synthetic_italic(&glyph_key).unwrap();
+
+        // The rightmost column stays empty when the whole lean fits
+        let stride = glyph.width as usize * 3;
+        for row in glyph.buf.chunks(stride) {
+            assert!(row[stride - 3..].iter().all(|&b| b == 0));
+        }
+        assert!(glyph.buf.iter().any(|&b| b != 0));
+    }
+
+    #[test]
+    fn default_emoji_font() {
+        let mut rasterizer = DwroteRasterizer::new(96., 96., 1., false).unwrap();
+        let desc = rasterizer.default_emoji_font().unwrap();
+        let key = rasterizer.load_font(&desc, Size::new(16.)).unwrap();
+
+        let glyph_key = GlyphKey { c: '\u{1F600}', font_key: key, size: Size::new(16.) };
+        let glyph = rasterizer.get_glyph(&glyph_key).unwrap();
+
+        assert_eq!(rasterizer.fallback_font(key, glyph_key.c), None);
+        assert!(!is_blank_glyph(&glyph));
+
+        // The face is yellow, so some visible pixels have to be far from gray
+        let color = rasterizer.get_glyph_color(&glyph_key, [0, 0, 0]).unwrap();
+        assert_eq!(color.buf.len(), (color.width * color.height * 4) as usize);
+        assert!(color.buf.chunks(4).any(|px| {
+            px[3] > 128 && (px[0] as i32 - px[2] as i32).abs() > 64
+        }));
+    }
+
+    #[test]
+    fn min_stem_at_tiny_sizes() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        rasterizer.set_min_stem(true);
+
+        let glyph_key = GlyphKey { c: 'l', font_key: key, size: Size::new(4.) };
+        let glyph = rasterizer.get_glyph(&glyph_key).unwrap();
+        assert!(glyph.buf.iter().any(|&b| b != 0));
+
+        let stride = glyph.width as usize * 3;
+        for row in glyph.buf.chunks(stride).filter(|row| row.iter().any(|&b| b != 0)) {
+            let coverage = (0..3)
+                .map(|i| {
+                    row.iter()
+                        .enumerate()
+                        .filter(|&(j, _)| j % 3 == i)
+                        .map(|(_, &b)| b as u32)
+                        .sum::<u32>()
+                })
+                .max()
+                .unwrap();
+            assert!(coverage >= 255);
+        }
+
+        // Stems of larger glyphs already cover a pixel
+        let large = GlyphKey { c: 'l', font_key: key, size: Size::new(24.) };
+        let boosted = rasterizer.get_glyph(&large).unwrap();
+        rasterizer.set_min_stem(false);
+        assert_eq!(rasterizer.get_glyph(&large).unwrap().buf, boosted.buf);
+    }
+
+    #[test]
+    fn get_glyph_transformed() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'H', font_key: key, size: Size::new(24.) };
+
+        let identity = Transform { m11: 1., m12: 0., m21: 0., m22: 1., dx: 0., dy: 0. };
+        let upright = rasterizer.get_glyph_transformed(&glyph_key, &identity).unwrap();
+
+        // Twice as wide, leaning right by a quarter pixel per pixel of height
+        let transform = Transform { m11: 2., m12: 0., m21: -0.25, m22: 1., dx: 0., dy: 0. };
+        let glyph = rasterizer.get_glyph_transformed(&glyph_key, &transform).unwrap();
+
+        // Both include a pixel of margin on each side
+        let ink_width = (upright.width - 2) as f32;
+        let ink_height = (upright.height - 2) as f32;
+        let expected = 2. * ink_width + 0.25 * ink_height + 2.;
+        assert!((glyph.width as f32 - expected).abs() <= 2.);
+        assert_eq!(glyph.height, upright.height);
+
+        // The top of the left stem is further right than its bottom
+        let leftmost_ink = |row: &[u8]| row.chunks(3).position(|pixel| pixel.iter().any(|&b| b != 0));
+        let rows = glyph.buf.chunks(glyph.width as usize * 3)
+            .filter_map(|row| leftmost_ink(row))
+            .collect::<Vec<_>>();
+        assert!(rows.first().unwrap() > rows.last().unwrap());
+    }
+
+    #[test]
+    fn lost_font_falls_back() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'A', font_key: key, size: Size::new(12.) };
+
+        // Simulate the font's file having been deleted
+        rasterizer.lose_font(key);
+
+        let glyph = rasterizer.get_glyph(&glyph_key).unwrap();
+        let fallback = rasterizer.fallback_font(key, 'A').unwrap();
+        assert!(fallback != key);
+        assert!(rasterizer.fonts[&fallback].family_name() != "Consolas");
+        assert!(!is_blank_glyph(&glyph));
+    }
+
+    #[test]
+    fn lost_font_without_fallback_is_an_error() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        rasterizer.freeze_fallback();
+        rasterizer.lose_font(key);
+
+        match rasterizer.get_glyph(&GlyphKey { c: 'A', font_key: key, size: Size::new(12.) }) {
+            Err(Error::FontUnavailable(family)) => assert_eq!(family, "Consolas"),
+            other => panic!("expected FontUnavailable, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn lost_font_is_unavailable_to_every_entry_point() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let glyph_key = GlyphKey { c: 'A', font_key: key, size: size };
+        rasterizer.freeze_fallback();
+        rasterizer.lose_font(key);
+
+        let unavailable = |result: Result<RasterizedGlyph, Error>| {
+            match result {
+                Err(Error::FontUnavailable(family)) => assert_eq!(family, "Consolas"),
+                other => panic!("expected FontUnavailable, got {:?}", other.map(|_| ())),
+            }
+        };
+        unavailable(rasterizer.get_glyph_transformed(&glyph_key, &super::shear_matrix(0.2)));
+        unavailable(rasterizer.get_glyph_subpixel(&glyph_key, 0.5));
+        unavailable(rasterizer.get_glyph_weighted(key, 'A', size, 450.));
+        unavailable(rasterizer.get_glyph_fit_height(key, 'A', 16));
+        unavailable(rasterizer.rasterize_batch_parallel(&[glyph_key]).remove(0));
+
+        match rasterizer.metrics(key, size) {
+            Err(Error::FontUnavailable(family)) => assert_eq!(family, "Consolas"),
+            other => panic!("expected FontUnavailable, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[test]
+    fn platform_errors_keep_fonts_whose_files_are_there() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'A', font_key: key, size: Size::new(12.) };
+
+        match rasterizer.font_error(key, Error::PlatformError("test")) {
+            Error::PlatformError("test") => (),
+            other => panic!("expected PlatformError, got {:?}", other),
+        }
+        assert!(rasterizer.get_glyph(&glyph_key).is_ok());
+        assert!(!rasterizer.lost.contains(&key));
+    }
+
+    #[test]
+    fn glyph_metrics_resolve_fallbacks() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let size = Size::new(12.);
+        let c = '\u{65E5}';
+
+        // Measuring before rasterizing has to find the same fallback as rasterizing does
+        let advance = rasterizer.glyph_advance(key, c, size).unwrap();
+        let fallback = rasterizer.fallback_font(key, c).unwrap();
+        let em = rasterizer.faces[&fallback].metrics().designUnitsPerEm as f32;
+        let gm = rasterizer.raw_glyph_metrics(fallback, c).unwrap();
+        assert!((advance - gm.advanceWidth as f32 * size.as_f32_pts() / em).abs() < 1e-4);
+
+        rasterizer.get_glyph(&GlyphKey { c: c, font_key: key, size: size }).unwrap();
+        assert_eq!(rasterizer.fallback_font(key, c), Some(fallback));
+        assert_eq!(rasterizer.glyph_advance(key, c, size).unwrap(), advance);
+    }
+
+    #[test]
+    fn loaded_fallbacks_match_the_style() {
+        let (mut rasterizer, regular) = rasterizer_with_font("Consolas");
+        let desc = FontDesc::new("Consolas", Style::Description {
+            slant: Slant::Normal,
+            weight: Weight::Bold,
+        });
+        let bold = rasterizer.load_font(&desc, Size::new(12.)).unwrap();
+        let c = '\u{65E5}';
+
+        for &key in &[regular, bold] {
+            rasterizer.get_glyph(&GlyphKey { c: c, font_key: key, size: Size::new(12.) }).unwrap();
+        }
+
+        // The regular font's fallback was loaded first, but it's for a different weight
+        let regular_fallback = rasterizer.fallback_font(regular, c).unwrap();
+        let bold_fallback = rasterizer.fallback_font(bold, c).unwrap();
+        assert!(regular_fallback != bold_fallback);
+        assert!(rasterizer.fonts[&bold_fallback].weight().to_u32() >=
+                rasterizer.fonts[&regular_fallback].weight().to_u32());
+    }
+
+    #[test]
+    fn cached_and_subpixel_entries_are_separate() {
+        let (mut rasterizer, key) = rasterizer_with_font("Consolas");
+        let glyph_key = GlyphKey { c: 'a', font_key: key, size: Size::new(12.) };
+
+        rasterizer.get_glyph_subpixel(&glyph_key, 0.).unwrap();
+        let (_, from_cache) = rasterizer.get_glyph_cached(&glyph_key).unwrap();
+        assert!(!from_cache);
     }
 }
@@ -0,0 +1,237 @@
+// Copyright 2016 Joe Wilm, The Alacritty Project Contributors
+//
+// Licensed under the Apache License, Version 2.0 (the "License");
+// you may not use this file except in compliance with the License.
+// You may obtain a copy of the License at
+//
+//     http://www.apache.org/licenses/LICENSE-2.0
+//
+// Unless required by applicable law or agreed to in writing, software
+// distributed under the License is distributed on an "AS IS" BASIS,
+// WITHOUT WARRANTIES OR CONDITIONS OF ANY KIND, either express or implied.
+// See the License for the specific language governing permissions and
+// limitations under the License.
+//
+//! Readers for raw OpenType tables the dwrote bindings don't expose
+//!
+//! All values in OpenType tables are big endian.
+
+/// Unwrap an `Option`, returning `None` from the enclosing function if it's empty
+macro_rules! try_opt {
+    ($e:expr) => {
+        match $e {
+            Some(value) => value,
+            None => return None,
+        }
+    }
+}
+
+#[inline]
+fn read_u16(data: &[u8], offset: usize) -> Option<u16> {
+    if offset + 2 > data.len() {
+        return None;
+    }
+
+    Some((data[offset] as u16) << 8 | data[offset + 1] as u16)
+}
+
+#[inline]
+fn read_u32(data: &[u8], offset: usize) -> Option<u32> {
+    let high = try_opt!(read_u16(data, offset)) as u32;
+    let low = try_opt!(read_u16(data, offset + 2)) as u32;
+    Some(high << 16 | low)
+}
+
+/// Whether the `loca` table uses 32 bit offsets, according to `head`
+pub fn long_loca_offsets(head: &[u8]) -> Option<bool> {
+    read_u16(head, 50).map(|format| format == 1)
+}
+
+/// Byte range of glyph `index` within the `glyf` table
+fn glyph_range(loca: &[u8], long_offsets: bool, index: u16) -> Option<(usize, usize)> {
+    let index = index as usize;
+    if long_offsets {
+        let start = try_opt!(read_u32(loca, index * 4)) as usize;
+        let end = try_opt!(read_u32(loca, index * 4 + 4)) as usize;
+        Some((start, end))
+    } else {
+        // Short offsets are stored halved
+        let start = try_opt!(read_u16(loca, index * 2)) as usize * 2;
+        let end = try_opt!(read_u16(loca, index * 2 + 2)) as usize * 2;
+        Some((start, end))
+    }
+}
+
+/// Number of closed contours in glyph `index` of a TrueType `glyf` table
+///
+/// Composite glyphs are counted as the sum of their components. Returns `None` if the tables
+/// are malformed.
+pub fn contour_count(glyf: &[u8], loca: &[u8], long_offsets: bool, index: u16) -> Option<usize> {
+    contour_count_nested(glyf, loca, long_offsets, index, 0)
+}
+
+fn contour_count_nested(
+    glyf: &[u8],
+    loca: &[u8],
+    long_offsets: bool,
+    index: u16,
+    depth: usize
+) -> Option<usize> {
+    // Guard against cyclic composites in broken fonts
+    const MAX_DEPTH: usize = 8;
+    const ARG_1_AND_2_ARE_WORDS: u16 = 0x0001;
+    const WE_HAVE_A_SCALE: u16 = 0x0008;
+    const MORE_COMPONENTS: u16 = 0x0020;
+    const WE_HAVE_AN_X_AND_Y_SCALE: u16 = 0x0040;
+    const WE_HAVE_A_TWO_BY_TWO: u16 = 0x0080;
+
+    if depth > MAX_DEPTH {
+        return None;
+    }
+
+    let (start, end) = try_opt!(glyph_range(loca, long_offsets, index));
+    if start == end {
+        // Glyphs without outlines, like space
+        return Some(0);
+    }
+
+    let contours = try_opt!(read_u16(glyf, start)) as i16;
+    if contours >= 0 {
+        return Some(contours as usize);
+    }
+
+    // Composite glyph; components follow the 10 byte header
+    let mut offset = start + 10;
+    let mut total = 0;
+    loop {
+        let flags = try_opt!(read_u16(glyf, offset));
+        let component = try_opt!(read_u16(glyf, offset + 2));
+        total += try_opt!(contour_count_nested(glyf, loca, long_offsets, component, depth + 1));
+
+        offset += 4;
+        offset += if flags & ARG_1_AND_2_ARE_WORDS != 0 { 4 } else { 2 };
+        if flags & WE_HAVE_A_SCALE != 0 {
+            offset += 2;
+        } else if flags & WE_HAVE_AN_X_AND_Y_SCALE != 0 {
+            offset += 4;
+        } else if flags & WE_HAVE_A_TWO_BY_TWO != 0 {
+            offset += 8;
+        }
+
+        if flags & MORE_COMPONENTS == 0 {
+            return Some(total);
+        }
+    }
+}
+
+/// `CPAL` palette entry that stands for the text color
+pub const FOREGROUND_PALETTE_ENTRY: u16 = 0xFFFF;
+
+/// Layers of glyph `index` in a `COLR` table, bottom layer first
+///
+/// Each layer is a glyph index and the `CPAL` palette entry to draw it in. Only the version 0
+/// layers are read. Returns `None` for glyphs without layers or if the table is malformed.
+pub fn color_layers(colr: &[u8], index: u16) -> Option<Vec<(u16, u16)>> {
+    let base_count = try_opt!(read_u16(colr, 2)) as usize;
+    let base_offset = try_opt!(read_u32(colr, 4)) as usize;
+    let layer_offset = try_opt!(read_u32(colr, 8)) as usize;
+
+    // Base glyph records are six bytes each, sorted by glyph index
+    let (mut low, mut high) = (0, base_count);
+    while low < high {
+        let mid = (low + high) / 2;
+        let record = base_offset + mid * 6;
+        let glyph = try_opt!(read_u16(colr, record));
+
+        if glyph < index {
+            low = mid + 1;
+        } else if glyph > index {
+            high = mid;
+        } else {
+            let first = try_opt!(read_u16(colr, record + 2)) as usize;
+            let count = try_opt!(read_u16(colr, record + 4)) as usize;
+
+            let mut layers = Vec::with_capacity(count);
+            for layer in first..first + count {
+                let record = layer_offset + layer * 4;
+                layers.push((try_opt!(read_u16(colr, record)), try_opt!(read_u16(colr, record + 2))));
+            }
+            return Some(layers);
+        }
+    }
+
+    None
+}
+
+/// RGBA color of `entry` in `palette` of a `CPAL` table
+///
+/// Returns `None` if either is out of range or the table is malformed.
+pub fn palette_color(cpal: &[u8], palette: u16, entry: u16) -> Option<[u8; 4]> {
+    let entries = try_opt!(read_u16(cpal, 2));
+    let palettes = try_opt!(read_u16(cpal, 4));
+    if palette >= palettes || entry >= entries {
+        return None;
+    }
+
+    let records = try_opt!(read_u32(cpal, 8)) as usize;
+    let first = try_opt!(read_u16(cpal, 12 + palette as usize * 2)) as usize;
+    let record = records + (first + entry as usize) * 4;
+    if record + 4 > cpal.len() {
+        return None;
+    }
+
+    // Colors are stored as BGRA
+    Some([cpal[record + 2], cpal[record + 1], cpal[record], cpal[record + 3]])
+}
+
+#[cfg(test)]
+mod tests {
+    /// Big endian bytes of `values`
+    fn be16(values: &[u16]) -> Vec<u8> {
+        values.iter().flat_map(|&v| vec![(v >> 8) as u8, v as u8]).collect()
+    }
+
+    #[test]
+    fn contour_count() {
+        // Glyph 0: two contours. Glyph 1: empty. Glyph 2: composite of glyph 0 twice.
+        let mut glyf = be16(&[2, 0, 0, 0, 0]);
+        glyf.extend(be16(&[0xFFFF, 0, 0, 0, 0]));
+        glyf.extend(be16(&[0x0020, 0, 0]));
+        glyf.extend(be16(&[0x0000, 0, 0]));
+
+        // Short offsets, halved
+        let loca = be16(&[0, 5, 5, 11]);
+
+        assert_eq!(super::contour_count(&glyf, &loca, false, 0), Some(2));
+        assert_eq!(super::contour_count(&glyf, &loca, false, 1), Some(0));
+        assert_eq!(super::contour_count(&glyf, &loca, false, 2), Some(4));
+        assert_eq!(super::contour_count(&glyf, &loca, false, 3), None);
+    }
+
+    #[test]
+    fn color_layers() {
+        // Glyphs 3 and 7 have color layers
+        let mut colr = be16(&[0, 2, 0, 14, 0, 26, 3]);
+        colr.extend(be16(&[3, 0, 2, 7, 2, 1]));
+        colr.extend(be16(&[10, 0, 11, 0xFFFF, 12, 1]));
+
+        assert_eq!(super::color_layers(&colr, 3), Some(vec![(10, 0), (11, 0xFFFF)]));
+        assert_eq!(super::color_layers(&colr, 7), Some(vec![(12, 1)]));
+        assert_eq!(super::color_layers(&colr, 5), None);
+        assert_eq!(super::color_layers(&colr[..30], 7), None);
+    }
+
+    #[test]
+    fn palette_color() {
+        // Two palettes of two entries; the second palette starts at record 2
+        let mut cpal = be16(&[0, 2, 2, 4, 0, 16, 0, 2]);
+        cpal.extend(vec![0, 0, 255, 255, 255, 0, 0, 255, 0, 255, 0, 128, 1, 2, 3, 4]);
+
+        assert_eq!(super::palette_color(&cpal, 0, 0), Some([255, 0, 0, 255]));
+        assert_eq!(super::palette_color(&cpal, 0, 1), Some([0, 0, 255, 255]));
+        assert_eq!(super::palette_color(&cpal, 1, 0), Some([0, 255, 0, 128]));
+        assert_eq!(super::palette_color(&cpal, 1, 1), Some([3, 2, 1, 4]));
+        assert_eq!(super::palette_color(&cpal, 2, 0), None);
+        assert_eq!(super::palette_color(&cpal, 0, 2), None);
+    }
+}
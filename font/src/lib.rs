@@ -33,8 +33,16 @@ extern crate core_foundation_sys;
 #[cfg(target_os = "macos")]
 extern crate core_graphics;
 
+#[cfg(target_os = "windows")]
+extern crate crossbeam;
 #[cfg(target_os = "windows")]
 extern crate dwrote;
+#[cfg(target_os = "windows")]
+extern crate winapi;
+#[cfg(all(test, target_os = "windows"))]
+extern crate gdi32;
+#[cfg(all(test, target_os = "windows"))]
+extern crate serde_json;
 
 extern crate euclid;
 extern crate libc;
@@ -62,6 +70,9 @@ pub use darwin::*;
 mod dwrite;
 #[cfg(target_os = "windows")]
 pub use dwrite::{DwroteRasterizer as Rasterizer, Error};
+#[cfg(target_os = "windows")]
+pub use dwrite::{BearingOverflow, ColorGlyph, ColorSpace, CoverageThreshold, GlyphMetrics, Glyphs,
+                 Hinting, NumericStyle, TabMode, Transform, UnderlineMetrics};
 
 #[derive(Debug, Clone, PartialEq, Eq, Hash)]
 pub struct FontDesc {
@@ -118,7 +129,9 @@ impl fmt::Display for FontDesc {
 }
 
 /// Identifier for a Font for use in maps/etc
-#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq)]
+///
+/// Keys are ordered by when they were handed out.
+#[derive(Debug, Clone, Copy, Hash, PartialEq, Eq, PartialOrd, Ord)]
 pub struct FontKey {
     token: u32,
 }
@@ -165,6 +178,7 @@ impl Size {
     }
 }
 
+#[derive(Clone)]
 pub struct RasterizedGlyph {
     pub c: char,
     pub width: i32,
@@ -174,6 +188,14 @@ pub struct RasterizedGlyph {
     pub buf: Vec<u8>,
 }
 
+/// Check whether `glyph` has no coverage at all
+///
+/// Spaces and zero width characters rasterize to blank glyphs, which don't need uploading to a
+/// texture.
+pub fn is_blank_glyph(glyph: &RasterizedGlyph) -> bool {
+    glyph.buf.iter().all(|&b| b == 0)
+}
+
 struct BufDebugger<'a>(&'a [u8]);
 
 impl<'a> fmt::Debug for BufDebugger<'a> {